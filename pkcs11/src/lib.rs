@@ -0,0 +1,723 @@
+//! PKCS#11 module exposing the certificates and keys of Serbian ID cards through PC/SC,
+//! built on the same card access code as rsid_reader.
+#![allow(non_snake_case)]
+
+mod token;
+
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+use std::sync::Mutex;
+use cryptoki_sys::*;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+use rsid_reader::idreader::pin::{self, PinStatus};
+use rsid_reader::idreader::sign;
+use token::{padded, Module, SignOperation};
+
+const MANUFACTURER: &str = "rsid_reader";
+const MIN_KEY_SIZE: CK_ULONG = 1024;
+const MAX_KEY_SIZE: CK_ULONG = 4096;
+const MECHANISMS: &[CK_MECHANISM_TYPE] = &[CKM_RSA_PKCS, CKM_SHA256_RSA_PKCS];
+
+static MODULE: Mutex<Option<Module>> = Mutex::new(None);
+
+/// Runs `f` on the initialized module, no panic crosses the FFI boundary.
+fn with_module<F>(f: F) -> CK_RV
+where
+    F: FnOnce(&mut Module) -> Result<(), CK_RV>,
+{
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut module = MODULE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match module.as_mut() {
+            Some(module) => f(module),
+            None => Err(CKR_CRYPTOKI_NOT_INITIALIZED),
+        }
+    }));
+    match result {
+        Ok(Ok(())) => CKR_OK,
+        Ok(Err(rv)) => rv,
+        Err(_) => CKR_GENERAL_ERROR,
+    }
+}
+
+/// Two call convention: a null `out` asks for the length only.
+unsafe fn write_output(data: &[u8], out: *mut CK_BYTE, out_len: *mut CK_ULONG) -> Result<(), CK_RV> {
+    if out_len.is_null() {
+        return Err(CKR_ARGUMENTS_BAD);
+    }
+    let available = *out_len as usize;
+    *out_len = data.len() as CK_ULONG;
+    if out.is_null() {
+        return Ok(());
+    }
+    if available < data.len() {
+        return Err(CKR_BUFFER_TOO_SMALL);
+    }
+    slice::from_raw_parts_mut(out, data.len()).copy_from_slice(data);
+    Ok(())
+}
+
+unsafe fn input<'a>(data: *const CK_BYTE, len: CK_ULONG) -> Result<&'a [u8], CK_RV> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(CKR_ARGUMENTS_BAD),
+        (false, len) => Ok(slice::from_raw_parts(data, len as usize)),
+    }
+}
+
+unsafe fn template(attributes: *const CK_ATTRIBUTE, count: CK_ULONG) -> Result<Vec<(CK_ATTRIBUTE_TYPE, Vec<u8>)>, CK_RV> {
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    if attributes.is_null() {
+        return Err(CKR_ARGUMENTS_BAD);
+    }
+    slice::from_raw_parts(attributes, count as usize).iter()
+        .map(|attribute| Ok((attribute.type_, input(attribute.pValue as *const CK_BYTE, attribute.ulValueLen)?.to_vec())))
+        .collect()
+}
+
+fn pin_error(status: PinStatus) -> CK_RV {
+    match status {
+        PinStatus::Verified => CKR_OK,
+        PinStatus::Remaining(_) => CKR_PIN_INCORRECT,
+        PinStatus::Blocked => CKR_PIN_LOCKED,
+    }
+}
+
+unsafe extern "C" fn C_Initialize(init_args: *mut c_void) -> CK_RV {
+    if !init_args.is_null() {
+        let args = &*(init_args as *const CK_C_INITIALIZE_ARGS);
+        if args.CreateMutex.is_some() && args.flags & CKF_OS_LOCKING_OK == 0 {
+            return CKR_CANT_LOCK;
+        }
+    }
+    let mut module = MODULE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if module.is_some() {
+        return CKR_CRYPTOKI_ALREADY_INITIALIZED;
+    }
+    *module = Some(Module::default());
+    CKR_OK
+}
+
+unsafe extern "C" fn C_Finalize(reserved: *mut c_void) -> CK_RV {
+    if !reserved.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let mut module = MODULE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match module.take() {
+        Some(_) => CKR_OK,
+        None => CKR_CRYPTOKI_NOT_INITIALIZED,
+    }
+}
+
+unsafe extern "C" fn C_GetInfo(info: *mut CK_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|_| {
+        *info = CK_INFO {
+            cryptokiVersion: CK_VERSION { major: 2, minor: 40 },
+            manufacturerID: padded(MANUFACTURER),
+            flags: 0,
+            libraryDescription: padded("Serbian eID PKCS#11 module"),
+            libraryVersion: CK_VERSION { major: 0, minor: 1 },
+        };
+        Ok(())
+    })
+}
+
+/// The only exported symbol, everything else is reached through the function list.
+///
+/// # Safety
+/// `list` has to be null or point to writable memory for one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn C_GetFunctionList(list: *mut *mut CK_FUNCTION_LIST) -> CK_RV {
+    if list.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    // Consumers only read the table
+    *list = &FUNCTION_LIST as *const CK_FUNCTION_LIST as *mut CK_FUNCTION_LIST;
+    CKR_OK
+}
+
+unsafe extern "C" fn C_GetSlotList(token_present: CK_BBOOL, slot_list: *mut CK_SLOT_ID, count: *mut CK_ULONG) -> CK_RV {
+    if count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        module.refresh_slots();
+        let mut slots = vec![];
+        for slot_id in 0..module.slots.len() as CK_SLOT_ID {
+            if token_present == CK_FALSE || module.token(slot_id).is_ok() {
+                slots.push(slot_id);
+            }
+        }
+        let available = *count as usize;
+        *count = slots.len() as CK_ULONG;
+        if slot_list.is_null() {
+            return Ok(());
+        }
+        if available < slots.len() {
+            return Err(CKR_BUFFER_TOO_SMALL);
+        }
+        slice::from_raw_parts_mut(slot_list, slots.len()).copy_from_slice(&slots);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetSlotInfo(slot_id: CK_SLOT_ID, info: *mut CK_SLOT_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        let present = module.token(slot_id).is_ok();
+        let slot = module.slot(slot_id)?;
+        let mut flags = CKF_REMOVABLE_DEVICE | CKF_HW_SLOT;
+        if present {
+            flags |= CKF_TOKEN_PRESENT;
+        }
+        *info = CK_SLOT_INFO {
+            slotDescription: padded(&slot.reader.to_string_lossy()),
+            manufacturerID: padded(MANUFACTURER),
+            flags,
+            hardwareVersion: CK_VERSION { major: 0, minor: 0 },
+            firmwareVersion: CK_VERSION { major: 0, minor: 0 },
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetTokenInfo(slot_id: CK_SLOT_ID, info: *mut CK_TOKEN_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        let session_count = module.sessions.values().filter(|session| session.slot == slot_id).count() as CK_ULONG;
+        let token = module.token(slot_id)?;
+        let mut flags = CKF_TOKEN_INITIALIZED | CKF_USER_PIN_INITIALIZED | CKF_LOGIN_REQUIRED | CKF_WRITE_PROTECTED;
+        match pin::pin_status(token.reader(), &token.card) {
+            Ok(PinStatus::Remaining(1)) => flags |= CKF_USER_PIN_COUNT_LOW | CKF_USER_PIN_FINAL_TRY,
+            Ok(PinStatus::Blocked) => flags |= CKF_USER_PIN_LOCKED,
+            _ => {}
+        }
+        *info = CK_TOKEN_INFO {
+            label: padded("Licna karta"),
+            manufacturerID: padded("MUP Republike Srbije"),
+            model: padded("Serbian eID"),
+            serialNumber: padded(&token.serial),
+            flags,
+            ulMaxSessionCount: CK_EFFECTIVELY_INFINITE,
+            ulSessionCount: session_count,
+            ulMaxRwSessionCount: 0,
+            ulRwSessionCount: 0,
+            ulMaxPinLen: pin::PIN_MAX_LENGTH as CK_ULONG,
+            ulMinPinLen: pin::PIN_MIN_LENGTH as CK_ULONG,
+            ulTotalPublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulTotalPrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            hardwareVersion: CK_VERSION { major: 0, minor: 0 },
+            firmwareVersion: CK_VERSION { major: 0, minor: 0 },
+            utcTime: padded(""),
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetMechanismList(slot_id: CK_SLOT_ID, list: *mut CK_MECHANISM_TYPE, count: *mut CK_ULONG) -> CK_RV {
+    if count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        module.token(slot_id)?;
+        let available = *count as usize;
+        *count = MECHANISMS.len() as CK_ULONG;
+        if list.is_null() {
+            return Ok(());
+        }
+        if available < MECHANISMS.len() {
+            return Err(CKR_BUFFER_TOO_SMALL);
+        }
+        slice::from_raw_parts_mut(list, MECHANISMS.len()).copy_from_slice(MECHANISMS);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetMechanismInfo(slot_id: CK_SLOT_ID, mechanism: CK_MECHANISM_TYPE, info: *mut CK_MECHANISM_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        module.token(slot_id)?;
+        if !MECHANISMS.contains(&mechanism) {
+            return Err(CKR_MECHANISM_INVALID);
+        }
+        *info = CK_MECHANISM_INFO { ulMinKeySize: MIN_KEY_SIZE, ulMaxKeySize: MAX_KEY_SIZE, flags: CKF_HW | CKF_SIGN };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_OpenSession(slot_id: CK_SLOT_ID, flags: CK_FLAGS, _application: *mut c_void, _notify: CK_NOTIFY, session: *mut CK_SESSION_HANDLE) -> CK_RV {
+    if session.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if flags & CKF_SERIAL_SESSION == 0 {
+        return CKR_SESSION_PARALLEL_NOT_SUPPORTED;
+    }
+    if flags & CKF_RW_SESSION != 0 {
+        return CKR_TOKEN_WRITE_PROTECTED;
+    }
+    with_module(|module| {
+        *session = module.open_session(slot_id, flags)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_CloseSession(session: CK_SESSION_HANDLE) -> CK_RV {
+    with_module(|module| {
+        let slot_id = module.session(session)?.slot;
+        module.sessions.remove(&session);
+        // Closing the last session logs the user out
+        if !module.sessions.values().any(|other| other.slot == slot_id) {
+            if let Ok(token) = module.token(slot_id) {
+                if token.logged_in {
+                    token.logout()?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_CloseAllSessions(slot_id: CK_SLOT_ID) -> CK_RV {
+    with_module(|module| {
+        module.slot(slot_id)?;
+        module.sessions.retain(|_, session| session.slot != slot_id);
+        if let Ok(token) = module.token(slot_id) {
+            if token.logged_in {
+                token.logout()?;
+            }
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetSessionInfo(session: CK_SESSION_HANDLE, info: *mut CK_SESSION_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        let (session, token) = module.session_token(session)?;
+        let state = if token.logged_in { CKS_RO_USER_FUNCTIONS } else { CKS_RO_PUBLIC_SESSION };
+        *info = CK_SESSION_INFO { slotID: session.slot, state, flags: session.flags, ulDeviceError: 0 };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_Login(session: CK_SESSION_HANDLE, user_type: CK_USER_TYPE, pin: *mut CK_UTF8CHAR, pin_len: CK_ULONG) -> CK_RV {
+    if user_type != CKU_USER {
+        return CKR_USER_TYPE_INVALID;
+    }
+    // A null PIN would mean a protected authentication path, readers with a PIN pad are not supported
+    let pin = match input(pin, pin_len) {
+        Ok(pin) if !pin.is_empty() => Zeroizing::new(String::from_utf8_lossy(pin).into_owned()),
+        _ => return CKR_ARGUMENTS_BAD,
+    };
+    with_module(|module| {
+        let (_, token) = module.session_token(session)?;
+        if token.logged_in {
+            return Err(CKR_USER_ALREADY_LOGGED_IN);
+        }
+        pin::validate_pin(&pin).map_err(|_| CKR_PIN_LEN_RANGE)?;
+        let status = pin::verify_pin(token.reader(), &token.card, &pin).map_err(|_| CKR_DEVICE_ERROR)?;
+        match status {
+            PinStatus::Verified => {
+                token.logged_in = true;
+                Ok(())
+            }
+            status => Err(pin_error(status)),
+        }
+    })
+}
+
+unsafe extern "C" fn C_Logout(session: CK_SESSION_HANDLE) -> CK_RV {
+    with_module(|module| {
+        let (_, token) = module.session_token(session)?;
+        if !token.logged_in {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+        token.logout()
+    })
+}
+
+unsafe extern "C" fn C_SetPIN(session: CK_SESSION_HANDLE, old_pin: *mut CK_UTF8CHAR, old_len: CK_ULONG, new_pin: *mut CK_UTF8CHAR, new_len: CK_ULONG) -> CK_RV {
+    let (old_pin, new_pin) = match (input(old_pin, old_len), input(new_pin, new_len)) {
+        (Ok(old_pin), Ok(new_pin)) => (
+            Zeroizing::new(String::from_utf8_lossy(old_pin).into_owned()),
+            Zeroizing::new(String::from_utf8_lossy(new_pin).into_owned()),
+        ),
+        _ => return CKR_ARGUMENTS_BAD,
+    };
+    with_module(|module| {
+        let (_, token) = module.session_token(session)?;
+        pin::validate_pin(&old_pin).map_err(|_| CKR_PIN_LEN_RANGE)?;
+        pin::validate_pin(&new_pin).map_err(|_| CKR_PIN_INVALID)?;
+        let status = pin::change_pin(token.reader(), &token.card, &old_pin, &new_pin).map_err(|_| CKR_DEVICE_ERROR)?;
+        match status {
+            PinStatus::Verified => Ok(()),
+            status => Err(pin_error(status)),
+        }
+    })
+}
+
+unsafe extern "C" fn C_GetAttributeValue(session: CK_SESSION_HANDLE, object: CK_OBJECT_HANDLE, attributes: *mut CK_ATTRIBUTE, count: CK_ULONG) -> CK_RV {
+    if attributes.is_null() && count > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        let (_, token) = module.session_token(session)?;
+        let object = token.object(object)?;
+        let mut rv = Ok(());
+        for attribute in slice::from_raw_parts_mut(attributes, count as usize) {
+            let value = match object.get(attribute.type_) {
+                Some(value) => value,
+                None => {
+                    attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                    rv = Err(CKR_ATTRIBUTE_TYPE_INVALID);
+                    continue;
+                }
+            };
+            if attribute.pValue.is_null() {
+                attribute.ulValueLen = value.len() as CK_ULONG;
+            } else if (attribute.ulValueLen as usize) < value.len() {
+                attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                rv = Err(CKR_BUFFER_TOO_SMALL);
+            } else {
+                slice::from_raw_parts_mut(attribute.pValue as *mut u8, value.len()).copy_from_slice(value);
+                attribute.ulValueLen = value.len() as CK_ULONG;
+            }
+        }
+        rv
+    })
+}
+
+unsafe extern "C" fn C_FindObjectsInit(session: CK_SESSION_HANDLE, attributes: *mut CK_ATTRIBUTE, count: CK_ULONG) -> CK_RV {
+    let template = match template(attributes, count) {
+        Ok(template) => template,
+        Err(rv) => return rv,
+    };
+    with_module(|module| {
+        let (session, token) = module.session_token(session)?;
+        if session.find.is_some() {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        let mut found: Vec<CK_OBJECT_HANDLE> = token.visible_objects()
+            .filter(|(_, object)| object.matches(&template))
+            .map(|(handle, _)| handle)
+            .collect();
+        // Handed out from the back
+        found.reverse();
+        session.find = Some(found);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_FindObjects(session: CK_SESSION_HANDLE, objects: *mut CK_OBJECT_HANDLE, max_count: CK_ULONG, count: *mut CK_ULONG) -> CK_RV {
+    if objects.is_null() || count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_module(|module| {
+        let found = module.session(session)?.find.as_mut().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        let mut returned = 0;
+        while returned < max_count as usize {
+            match found.pop() {
+                Some(handle) => *objects.add(returned) = handle,
+                None => break,
+            }
+            returned += 1;
+        }
+        *count = returned as CK_ULONG;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_FindObjectsFinal(session: CK_SESSION_HANDLE) -> CK_RV {
+    with_module(|module| {
+        let session = module.session(session)?;
+        session.find.take().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_SignInit(session: CK_SESSION_HANDLE, mechanism: *mut CK_MECHANISM, key: CK_OBJECT_HANDLE) -> CK_RV {
+    if mechanism.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let mechanism = (*mechanism).mechanism;
+    with_module(|module| {
+        let (session, token) = module.session_token(session)?;
+        if session.sign.is_some() {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        if !MECHANISMS.contains(&mechanism) {
+            return Err(CKR_MECHANISM_INVALID);
+        }
+        if !token.logged_in {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+        let object = token.object(key).map_err(|_| CKR_KEY_HANDLE_INVALID)?;
+        let key_reference = object.key_reference.ok_or(CKR_KEY_TYPE_INCONSISTENT)?;
+        session.sign = Some(SignOperation::new(mechanism, key_reference, object.modulus_len));
+        Ok(())
+    })
+}
+
+/// Finishes the signature over everything passed to the operation so far.
+unsafe fn finish_sign(module: &mut Module, session: CK_SESSION_HANDLE, signature: *mut CK_BYTE, signature_len: *mut CK_ULONG) -> Result<(), CK_RV> {
+    let (session, token) = module.session_token(session)?;
+    let operation = session.sign.as_ref().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+    if signature_len.is_null() {
+        return Err(CKR_ARGUMENTS_BAD);
+    }
+    // Only asking for the length, or a buffer that can't hold it, keeps the operation active
+    if signature.is_null() {
+        *signature_len = operation.modulus_len as CK_ULONG;
+        return Ok(());
+    }
+    if (*signature_len as usize) < operation.modulus_len {
+        *signature_len = operation.modulus_len as CK_ULONG;
+        return Err(CKR_BUFFER_TOO_SMALL);
+    }
+
+    let operation = session.sign.take().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+    let digest_info = match operation.mechanism {
+        CKM_SHA256_RSA_PKCS => sign::sha256_digest_info(&Sha256::digest(&operation.data).into()),
+        _ => operation.data,
+    };
+    // PKCS#1 v1.5 padding needs at least 11 bytes
+    if digest_info.len() + 11 > operation.modulus_len {
+        return Err(CKR_DATA_LEN_RANGE);
+    }
+    let result = sign::sign_digest_info(token.reader(), &token.card, operation.key_reference, &digest_info)
+        .map_err(|_| CKR_DEVICE_ERROR)?;
+    write_output(&result, signature, signature_len)
+}
+
+unsafe extern "C" fn C_Sign(session: CK_SESSION_HANDLE, data: *mut CK_BYTE, data_len: CK_ULONG, signature: *mut CK_BYTE, signature_len: *mut CK_ULONG) -> CK_RV {
+    let data = match input(data, data_len) {
+        Ok(data) => data,
+        Err(rv) => return rv,
+    };
+    with_module(|module| {
+        let operation = module.session(session)?.sign.as_mut().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        operation.set_data(data)?;
+        finish_sign(module, session, signature, signature_len)
+    })
+}
+
+unsafe extern "C" fn C_SignUpdate(session: CK_SESSION_HANDLE, part: *mut CK_BYTE, part_len: CK_ULONG) -> CK_RV {
+    let part = match input(part, part_len) {
+        Ok(part) => part,
+        Err(rv) => return rv,
+    };
+    with_module(|module| {
+        let operation = module.session(session)?.sign.as_mut().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        operation.update(part);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_SignFinal(session: CK_SESSION_HANDLE, signature: *mut CK_BYTE, signature_len: *mut CK_ULONG) -> CK_RV {
+    with_module(|module| finish_sign(module, session, signature, signature_len))
+}
+
+unsafe extern "C" fn C_GetFunctionStatus(_session: CK_SESSION_HANDLE) -> CK_RV {
+    CKR_FUNCTION_NOT_PARALLEL
+}
+
+unsafe extern "C" fn C_CancelFunction(_session: CK_SESSION_HANDLE) -> CK_RV {
+    CKR_FUNCTION_NOT_PARALLEL
+}
+
+macro_rules! not_supported {
+    ($($name:ident($($arg:ty),*);)*) => {
+        $(unsafe extern "C" fn $name($(_: $arg),*) -> CK_RV {
+            CKR_FUNCTION_NOT_SUPPORTED
+        })*
+    };
+}
+
+not_supported! {
+    C_InitToken(CK_SLOT_ID, *mut CK_UTF8CHAR, CK_ULONG, *mut CK_UTF8CHAR);
+    C_InitPIN(CK_SESSION_HANDLE, *mut CK_UTF8CHAR, CK_ULONG);
+    C_GetOperationState(CK_SESSION_HANDLE, *mut CK_BYTE, *mut CK_ULONG);
+    C_SetOperationState(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, CK_OBJECT_HANDLE, CK_OBJECT_HANDLE);
+    C_CreateObject(CK_SESSION_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    C_CopyObject(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    C_DestroyObject(CK_SESSION_HANDLE, CK_OBJECT_HANDLE);
+    C_GetObjectSize(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ULONG);
+    C_SetAttributeValue(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG);
+    C_EncryptInit(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    C_Encrypt(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_EncryptUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_EncryptFinal(CK_SESSION_HANDLE, *mut CK_BYTE, *mut CK_ULONG);
+    C_DecryptInit(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    C_Decrypt(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DecryptUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DecryptFinal(CK_SESSION_HANDLE, *mut CK_BYTE, *mut CK_ULONG);
+    C_DigestInit(CK_SESSION_HANDLE, *mut CK_MECHANISM);
+    C_Digest(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DigestUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG);
+    C_DigestKey(CK_SESSION_HANDLE, CK_OBJECT_HANDLE);
+    C_DigestFinal(CK_SESSION_HANDLE, *mut CK_BYTE, *mut CK_ULONG);
+    C_SignRecoverInit(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    C_SignRecover(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_VerifyInit(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    C_Verify(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, CK_ULONG);
+    C_VerifyUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG);
+    C_VerifyFinal(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG);
+    C_VerifyRecoverInit(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    C_VerifyRecover(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DigestEncryptUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DecryptDigestUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_SignEncryptUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_DecryptVerifyUpdate(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG);
+    C_GenerateKey(CK_SESSION_HANDLE, *mut CK_MECHANISM, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    C_GenerateKeyPair(CK_SESSION_HANDLE, *mut CK_MECHANISM, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE, *mut CK_OBJECT_HANDLE);
+    C_WrapKey(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE, CK_OBJECT_HANDLE, *mut CK_BYTE, *mut CK_ULONG);
+    C_UnwrapKey(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE, *mut CK_BYTE, CK_ULONG, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    C_DeriveKey(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    C_SeedRandom(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG);
+    C_GenerateRandom(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG);
+    C_WaitForSlotEvent(CK_FLAGS, *mut CK_SLOT_ID, *mut c_void);
+}
+
+static FUNCTION_LIST: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+    version: CK_VERSION { major: 2, minor: 40 },
+    C_Initialize: Some(C_Initialize),
+    C_Finalize: Some(C_Finalize),
+    C_GetInfo: Some(C_GetInfo),
+    C_GetFunctionList: Some(C_GetFunctionList),
+    C_GetSlotList: Some(C_GetSlotList),
+    C_GetSlotInfo: Some(C_GetSlotInfo),
+    C_GetTokenInfo: Some(C_GetTokenInfo),
+    C_GetMechanismList: Some(C_GetMechanismList),
+    C_GetMechanismInfo: Some(C_GetMechanismInfo),
+    C_InitToken: Some(C_InitToken),
+    C_InitPIN: Some(C_InitPIN),
+    C_SetPIN: Some(C_SetPIN),
+    C_OpenSession: Some(C_OpenSession),
+    C_CloseSession: Some(C_CloseSession),
+    C_CloseAllSessions: Some(C_CloseAllSessions),
+    C_GetSessionInfo: Some(C_GetSessionInfo),
+    C_GetOperationState: Some(C_GetOperationState),
+    C_SetOperationState: Some(C_SetOperationState),
+    C_Login: Some(C_Login),
+    C_Logout: Some(C_Logout),
+    C_CreateObject: Some(C_CreateObject),
+    C_CopyObject: Some(C_CopyObject),
+    C_DestroyObject: Some(C_DestroyObject),
+    C_GetObjectSize: Some(C_GetObjectSize),
+    C_GetAttributeValue: Some(C_GetAttributeValue),
+    C_SetAttributeValue: Some(C_SetAttributeValue),
+    C_FindObjectsInit: Some(C_FindObjectsInit),
+    C_FindObjects: Some(C_FindObjects),
+    C_FindObjectsFinal: Some(C_FindObjectsFinal),
+    C_EncryptInit: Some(C_EncryptInit),
+    C_Encrypt: Some(C_Encrypt),
+    C_EncryptUpdate: Some(C_EncryptUpdate),
+    C_EncryptFinal: Some(C_EncryptFinal),
+    C_DecryptInit: Some(C_DecryptInit),
+    C_Decrypt: Some(C_Decrypt),
+    C_DecryptUpdate: Some(C_DecryptUpdate),
+    C_DecryptFinal: Some(C_DecryptFinal),
+    C_DigestInit: Some(C_DigestInit),
+    C_Digest: Some(C_Digest),
+    C_DigestUpdate: Some(C_DigestUpdate),
+    C_DigestKey: Some(C_DigestKey),
+    C_DigestFinal: Some(C_DigestFinal),
+    C_SignInit: Some(C_SignInit),
+    C_Sign: Some(C_Sign),
+    C_SignUpdate: Some(C_SignUpdate),
+    C_SignFinal: Some(C_SignFinal),
+    C_SignRecoverInit: Some(C_SignRecoverInit),
+    C_SignRecover: Some(C_SignRecover),
+    C_VerifyInit: Some(C_VerifyInit),
+    C_Verify: Some(C_Verify),
+    C_VerifyUpdate: Some(C_VerifyUpdate),
+    C_VerifyFinal: Some(C_VerifyFinal),
+    C_VerifyRecoverInit: Some(C_VerifyRecoverInit),
+    C_VerifyRecover: Some(C_VerifyRecover),
+    C_DigestEncryptUpdate: Some(C_DigestEncryptUpdate),
+    C_DecryptDigestUpdate: Some(C_DecryptDigestUpdate),
+    C_SignEncryptUpdate: Some(C_SignEncryptUpdate),
+    C_DecryptVerifyUpdate: Some(C_DecryptVerifyUpdate),
+    C_GenerateKey: Some(C_GenerateKey),
+    C_GenerateKeyPair: Some(C_GenerateKeyPair),
+    C_WrapKey: Some(C_WrapKey),
+    C_UnwrapKey: Some(C_UnwrapKey),
+    C_DeriveKey: Some(C_DeriveKey),
+    C_SeedRandom: Some(C_SeedRandom),
+    C_GenerateRandom: Some(C_GenerateRandom),
+    C_GetFunctionStatus: Some(C_GetFunctionStatus),
+    C_CancelFunction: Some(C_CancelFunction),
+    C_WaitForSlotEvent: Some(C_WaitForSlotEvent),
+};
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use super::*;
+
+    /// The module is global, so one test runs the whole sequence. It needs no card and no PC/SC service.
+    #[test]
+    fn function_list_without_a_card() {
+        unsafe {
+            let mut list = ptr::null_mut();
+            assert_eq!(C_GetFunctionList(&mut list), CKR_OK);
+            let list = &*list;
+            let mut info: CK_INFO = std::mem::zeroed();
+
+            assert_eq!(list.C_GetInfo.unwrap()(&mut info), CKR_CRYPTOKI_NOT_INITIALIZED);
+            assert_eq!(list.C_Initialize.unwrap()(ptr::null_mut()), CKR_OK);
+            assert_eq!(list.C_Initialize.unwrap()(ptr::null_mut()), CKR_CRYPTOKI_ALREADY_INITIALIZED);
+
+            assert_eq!(list.C_GetInfo.unwrap()(&mut info), CKR_OK);
+            assert_eq!(info.manufacturerID, padded(MANUFACTURER));
+
+            let mut count = 0;
+            assert_eq!(list.C_GetSlotList.unwrap()(CK_FALSE, ptr::null_mut(), &mut count), CKR_OK);
+            let mut session = 0;
+            let slot_id = count + 1;
+            let rv = list.C_OpenSession.unwrap()(slot_id, CKF_SERIAL_SESSION, ptr::null_mut(), None, &mut session);
+            assert_eq!(rv, CKR_SLOT_ID_INVALID);
+
+            let mut data = *b"data";
+            let mut signature_len = 0;
+            let rv = list.C_Sign.unwrap()(1, data.as_mut_ptr(), data.len() as CK_ULONG, ptr::null_mut(), &mut signature_len);
+            assert_eq!(rv, CKR_SESSION_HANDLE_INVALID);
+            assert_eq!(list.C_SignUpdate.unwrap()(1, ptr::null_mut(), 4), CKR_ARGUMENTS_BAD);
+            assert_eq!(list.C_DigestInit.unwrap()(1, ptr::null_mut()), CKR_FUNCTION_NOT_SUPPORTED);
+
+            assert_eq!(list.C_Finalize.unwrap()(ptr::null_mut()), CKR_OK);
+            assert_eq!(list.C_Finalize.unwrap()(ptr::null_mut()), CKR_CRYPTOKI_NOT_INITIALIZED);
+        }
+    }
+
+    #[test]
+    fn output_asks_for_the_length_first() {
+        let mut len = 0;
+        unsafe {
+            assert_eq!(write_output(b"signature", ptr::null_mut(), &mut len), Ok(()));
+            assert_eq!(len, 9);
+            let mut small = [0u8; 4];
+            len = small.len() as CK_ULONG;
+            assert_eq!(write_output(b"signature", small.as_mut_ptr(), &mut len), Err(CKR_BUFFER_TOO_SMALL));
+            let mut out = [0u8; 9];
+            assert_eq!(write_output(b"signature", out.as_mut_ptr(), &mut len), Ok(()));
+            assert_eq!(&out, b"signature");
+        }
+    }
+}
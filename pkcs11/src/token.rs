@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use cryptoki_sys::*;
+use der::Encode;
+use pcsc::{Card, Context, Disposition, Protocols, Scope, ShareMode};
+use rsa::traits::PublicKeyParts;
+use rsid_reader::idreader::certificate::{read_certificate, AUTH_CERT_FILE, SIGNING_CERT_FILE};
+use rsid_reader::idreader::reader::{CardReader, PersonalId};
+use rsid_reader::idreader::sign::{AUTH_KEY_REFERENCE, SIGNING_KEY_REFERENCE};
+use rsid_reader::signature;
+
+/// Key pairs on the card: label, certificate file and key reference
+const KEYS: &[(&str, &[u8], u8)] = &[
+    ("Authentication", AUTH_CERT_FILE, AUTH_KEY_REFERENCE),
+    ("Signing", SIGNING_CERT_FILE, SIGNING_KEY_REFERENCE),
+];
+
+fn ulong(value: CK_ULONG) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+fn boolean(value: bool) -> Vec<u8> {
+    vec![if value { CK_TRUE } else { CK_FALSE }]
+}
+
+/// Space padded, not NUL terminated, as PKCS#11 info structures expect.
+pub fn padded<const N: usize>(text: &str) -> [u8; N] {
+    let mut out = [b' '; N];
+    let len = text.len().min(N);
+    out[..len].copy_from_slice(&text.as_bytes()[..len]);
+    out
+}
+
+pub struct Object {
+    pub attributes: Vec<(CK_ATTRIBUTE_TYPE, Vec<u8>)>,
+    /// Only visible after login
+    pub private: bool,
+    /// Card key reference of a private key
+    pub key_reference: Option<u8>,
+    pub modulus_len: usize,
+}
+
+impl Object {
+    pub fn get(&self, attribute: CK_ATTRIBUTE_TYPE) -> Option<&[u8]> {
+        self.attributes.iter().find(|(kind, _)| *kind == attribute).map(|(_, value)| value.as_slice())
+    }
+
+    pub fn matches(&self, template: &[(CK_ATTRIBUTE_TYPE, Vec<u8>)]) -> bool {
+        template.iter().all(|(kind, value)| self.get(*kind) == Some(value.as_slice()))
+    }
+}
+
+/// Certificate, public and private key objects for one key pair.
+fn key_objects(label: &str, key_reference: u8, certificate_der: Vec<u8>) -> Result<Vec<Object>, String> {
+    let certificate = signature::parse_certificate(&certificate_der)?;
+    let key = signature::rsa_public_key(&certificate)?;
+    let subject = certificate.tbs_certificate.subject.to_der().map_err(|err| err.to_string())?;
+    let issuer = certificate.tbs_certificate.issuer.to_der().map_err(|err| err.to_string())?;
+    let serial = certificate.tbs_certificate.serial_number.to_der().map_err(|err| err.to_string())?;
+    let modulus = key.n().to_bytes_be();
+    let exponent = key.e().to_bytes_be();
+    let common = vec![
+        (CKA_TOKEN, boolean(true)),
+        (CKA_MODIFIABLE, boolean(false)),
+        (CKA_LABEL, label.as_bytes().to_vec()),
+        (CKA_ID, vec![key_reference]),
+        (CKA_SUBJECT, subject.clone()),
+    ];
+
+    let certificate = Object {
+        attributes: [common.clone(), vec![
+            (CKA_CLASS, ulong(CKO_CERTIFICATE)),
+            (CKA_PRIVATE, boolean(false)),
+            (CKA_CERTIFICATE_TYPE, ulong(CKC_X_509)),
+            (CKA_TRUSTED, boolean(false)),
+            (CKA_ISSUER, issuer),
+            (CKA_SERIAL_NUMBER, serial),
+            (CKA_VALUE, certificate_der),
+        ]].concat(),
+        private: false,
+        key_reference: None,
+        modulus_len: modulus.len(),
+    };
+    let public_key = Object {
+        attributes: [common.clone(), vec![
+            (CKA_CLASS, ulong(CKO_PUBLIC_KEY)),
+            (CKA_PRIVATE, boolean(false)),
+            (CKA_KEY_TYPE, ulong(CKK_RSA)),
+            (CKA_VERIFY, boolean(true)),
+            (CKA_ENCRYPT, boolean(false)),
+            (CKA_WRAP, boolean(false)),
+            (CKA_MODULUS, modulus.clone()),
+            (CKA_MODULUS_BITS, ulong(key.n().bits() as CK_ULONG)),
+            (CKA_PUBLIC_EXPONENT, exponent.clone()),
+        ]].concat(),
+        private: false,
+        key_reference: None,
+        modulus_len: modulus.len(),
+    };
+    let private_key = Object {
+        attributes: [common, vec![
+            (CKA_CLASS, ulong(CKO_PRIVATE_KEY)),
+            (CKA_PRIVATE, boolean(true)),
+            (CKA_KEY_TYPE, ulong(CKK_RSA)),
+            (CKA_SIGN, boolean(true)),
+            (CKA_SIGN_RECOVER, boolean(false)),
+            (CKA_DECRYPT, boolean(false)),
+            (CKA_UNWRAP, boolean(false)),
+            (CKA_DERIVE, boolean(false)),
+            (CKA_SENSITIVE, boolean(true)),
+            (CKA_ALWAYS_SENSITIVE, boolean(true)),
+            (CKA_EXTRACTABLE, boolean(false)),
+            (CKA_NEVER_EXTRACTABLE, boolean(true)),
+            (CKA_ALWAYS_AUTHENTICATE, boolean(false)),
+            (CKA_MODULUS, modulus.clone()),
+            (CKA_PUBLIC_EXPONENT, exponent),
+        ]].concat(),
+        private: true,
+        key_reference: Some(key_reference),
+        modulus_len: modulus.len(),
+    };
+    Ok(vec![certificate, public_key, private_key])
+}
+
+pub struct Token {
+    pub card: Card,
+    card_reader: Box<dyn CardReader>,
+    pub objects: Vec<Object>,
+    pub serial: String,
+    pub logged_in: bool,
+}
+
+impl Token {
+    /// Connects to the card in the reader and reads its certificates, `None` when the reader is empty.
+    fn connect(context: &Context, reader: &CStr) -> Result<Option<Token>, CK_RV> {
+        let card = match context.connect(reader, ShareMode::Shared, Protocols::ANY) {
+            Ok(card) => card,
+            Err(pcsc::Error::NoSmartcard) | Err(pcsc::Error::RemovedCard) => return Ok(None),
+            Err(_) => return Err(CKR_DEVICE_ERROR),
+        };
+        let card_reader = PersonalId::new(&card).map_err(|_| CKR_TOKEN_NOT_RECOGNIZED)?.card_reader;
+
+        let mut objects = vec![];
+        let mut serial = String::new();
+        for (label, file, key_reference) in KEYS {
+            // A card without one of the certificates still offers the other key
+            let certificate_der = match read_certificate(card_reader.as_ref(), &card, file) {
+                Ok(der) => der,
+                Err(_) => continue,
+            };
+            if let Ok(certificate) = signature::parse_certificate(&certificate_der) {
+                serial = certificate.tbs_certificate.serial_number.as_bytes().iter().map(|b| format!("{:02X}", b)).collect();
+            }
+            objects.extend(key_objects(label, *key_reference, certificate_der).map_err(|_| CKR_DEVICE_ERROR)?);
+        }
+        if serial.len() > 16 {
+            serial = serial[serial.len() - 16..].to_string();
+        }
+        Ok(Some(Token { card, card_reader, objects, serial, logged_in: false }))
+    }
+
+    /// Card access for the generation told from the ATR when the card was connected
+    pub fn reader(&self) -> &dyn CardReader {
+        self.card_reader.as_ref()
+    }
+
+    /// Objects the current login state allows to see, as (handle, object)
+    pub fn visible_objects(&self) -> impl Iterator<Item = (CK_OBJECT_HANDLE, &Object)> {
+        self.objects.iter().enumerate()
+            .filter(move |(_, object)| self.logged_in || !object.private)
+            .map(|(index, object)| (index as CK_OBJECT_HANDLE + 1, object))
+    }
+
+    pub fn object(&self, handle: CK_OBJECT_HANDLE) -> Result<&Object, CK_RV> {
+        self.visible_objects().find(|(found, _)| *found == handle).map(|(_, object)| object).ok_or(CKR_OBJECT_HANDLE_INVALID)
+    }
+
+    /// Resetting the card is the only way to drop the PIN verification.
+    pub fn logout(&mut self) -> Result<(), CK_RV> {
+        self.logged_in = false;
+        self.card.reconnect(ShareMode::Shared, Protocols::ANY, Disposition::ResetCard).map_err(|_| CKR_DEVICE_ERROR)
+    }
+}
+
+pub struct Slot {
+    pub reader: CString,
+    pub token: Option<Token>,
+}
+
+pub struct SignOperation {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub key_reference: u8,
+    pub modulus_len: usize,
+    pub data: Vec<u8>,
+    /// `C_SignUpdate` was called, so only `C_SignFinal` may finish the operation
+    multi_part: bool,
+}
+
+impl SignOperation {
+    pub fn new(mechanism: CK_MECHANISM_TYPE, key_reference: u8, modulus_len: usize) -> SignOperation {
+        SignOperation { mechanism, key_reference, modulus_len, data: vec![], multi_part: false }
+    }
+
+    /// All the data at once, for `C_Sign`. Repeated calls of the two call convention pass the same data again.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), CK_RV> {
+        if self.multi_part {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        self.data = data.to_vec();
+        Ok(())
+    }
+
+    /// One more part, for `C_SignUpdate`
+    pub fn update(&mut self, part: &[u8]) {
+        self.multi_part = true;
+        self.data.extend_from_slice(part);
+    }
+}
+
+pub struct Session {
+    pub slot: CK_SLOT_ID,
+    pub flags: CK_FLAGS,
+    pub find: Option<Vec<CK_OBJECT_HANDLE>>,
+    pub sign: Option<SignOperation>,
+}
+
+#[derive(Default)]
+pub struct Module {
+    context: Option<Context>,
+    pub slots: Vec<Slot>,
+    pub sessions: HashMap<CK_SESSION_HANDLE, Session>,
+    next_session: CK_SESSION_HANDLE,
+}
+
+impl Module {
+    /// Adds readers connected since the last call. Slots are never removed so their IDs stay stable.
+    /// Without a running PC/SC service there are simply no slots.
+    pub fn refresh_slots(&mut self) {
+        if self.context.is_none() {
+            self.context = Context::establish(Scope::User).ok();
+        }
+        let context = match &self.context {
+            Some(context) => context,
+            None => return,
+        };
+        let readers = match context.list_readers_owned() {
+            Ok(readers) => readers,
+            Err(_) => return,
+        };
+        for reader in readers {
+            if !self.slots.iter().any(|slot| slot.reader == reader) {
+                self.slots.push(Slot { reader, token: None });
+            }
+        }
+    }
+
+    pub fn slot(&self, slot_id: CK_SLOT_ID) -> Result<&Slot, CK_RV> {
+        self.slots.get(slot_id as usize).ok_or(CKR_SLOT_ID_INVALID)
+    }
+
+    /// The token in the slot, connecting to a newly inserted card and forgetting a removed one.
+    pub fn token(&mut self, slot_id: CK_SLOT_ID) -> Result<&mut Token, CK_RV> {
+        let context = self.context.as_ref().ok_or(CKR_SLOT_ID_INVALID)?;
+        let slot = self.slots.get_mut(slot_id as usize).ok_or(CKR_SLOT_ID_INVALID)?;
+
+        let removed = match &slot.token {
+            Some(token) => token.card.status2_owned().is_err(),
+            None => false,
+        };
+        if removed {
+            slot.token = None;
+            self.sessions.retain(|_, session| session.slot != slot_id);
+        }
+        if slot.token.is_none() {
+            slot.token = Token::connect(context, &slot.reader)?;
+        }
+        slot.token.as_mut().ok_or(CKR_TOKEN_NOT_PRESENT)
+    }
+
+    pub fn open_session(&mut self, slot_id: CK_SLOT_ID, flags: CK_FLAGS) -> Result<CK_SESSION_HANDLE, CK_RV> {
+        self.token(slot_id)?;
+        self.next_session += 1;
+        self.sessions.insert(self.next_session, Session { slot: slot_id, flags, find: None, sign: None });
+        Ok(self.next_session)
+    }
+
+    pub fn session(&mut self, handle: CK_SESSION_HANDLE) -> Result<&mut Session, CK_RV> {
+        self.sessions.get_mut(&handle).ok_or(CKR_SESSION_HANDLE_INVALID)
+    }
+
+    /// The session and the token it was opened on
+    pub fn session_token(&mut self, handle: CK_SESSION_HANDLE) -> Result<(&mut Session, &mut Token), CK_RV> {
+        let slot_id = self.session(handle)?.slot;
+        self.token(slot_id).map_err(|_| CKR_DEVICE_REMOVED)?;
+        let session = self.sessions.get_mut(&handle).ok_or(CKR_SESSION_HANDLE_INVALID)?;
+        let token = self.slots[slot_id as usize].token.as_mut().ok_or(CKR_DEVICE_REMOVED)?;
+        Ok((session, token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_rejects_data_after_updates() {
+        let mut operation = SignOperation::new(CKM_SHA256_RSA_PKCS, SIGNING_KEY_REFERENCE, 256);
+        operation.update(b"first ");
+        operation.update(b"second");
+        assert_eq!(operation.set_data(b"other"), Err(CKR_OPERATION_ACTIVE));
+        assert_eq!(operation.data, b"first second");
+    }
+
+    #[test]
+    fn sign_data_is_replaced_by_a_repeated_call() {
+        let mut operation = SignOperation::new(CKM_RSA_PKCS, AUTH_KEY_REFERENCE, 256);
+        operation.set_data(b"digest info").unwrap();
+        operation.set_data(b"digest info").unwrap();
+        assert_eq!(operation.data, b"digest info");
+    }
+
+    #[test]
+    fn padded_fills_with_spaces_and_truncates() {
+        assert_eq!(&padded::<8>("rsid"), b"rsid    ");
+        assert_eq!(&padded::<4>("rsid_reader"), b"rsid");
+    }
+
+    #[test]
+    fn object_matches_template() {
+        let object = Object {
+            attributes: vec![(CKA_CLASS, ulong(CKO_PRIVATE_KEY)), (CKA_ID, vec![SIGNING_KEY_REFERENCE])],
+            private: true,
+            key_reference: Some(SIGNING_KEY_REFERENCE),
+            modulus_len: 256,
+        };
+        assert!(object.matches(&[]));
+        assert!(object.matches(&[(CKA_CLASS, ulong(CKO_PRIVATE_KEY))]));
+        assert!(!object.matches(&[(CKA_CLASS, ulong(CKO_CERTIFICATE))]));
+        assert!(!object.matches(&[(CKA_LABEL, b"Signing".to_vec())]));
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::SystemTime;
+use pcsc::Card;
+use crate::idreader::auth;
+use crate::idreader::reader::PersonalId;
+use crate::signature::chain::{KeyPurpose, Verdict};
+use super::certs::{trust_store, TrustOptions};
+use super::pin::login;
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if let Some(invalid) = text.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex in challenge: {}", invalid));
+    }
+    if !text.len().is_multiple_of(2) {
+        return Err("Challenge must have an even number of hex digits".to_string());
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|err| err.to_string()))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Proves the card is present: the card answers a challenge with its authentication key,
+/// the answer is checked against the certificate read from the same card and the certificate
+/// against the trusted CAs.
+pub fn authenticate(personal_id: &PersonalId, card: &Card, challenge: Option<&str>, options: &TrustOptions) -> Result<Verdict, String> {
+    let challenge = match challenge {
+        Some(challenge) => parse_hex(challenge)?,
+        None => auth::new_challenge().to_vec(),
+    };
+    if challenge.len() < 16 {
+        return Err("Challenge must be at least 16 bytes".to_string());
+    }
+    let store = trust_store(options)?;
+
+    login(personal_id, card)?;
+    let response = auth::respond(personal_id.card_reader.as_ref(), card, &challenge)?;
+    let certificate = auth::verify(&challenge, &response)?;
+
+    println!("Challenge: {}", hex(&challenge));
+    println!("Response: {}", hex(&response.signature));
+    println!("Card authenticated: {}", certificate.tbs_certificate.subject);
+
+    let validation = store.validate(&certificate, KeyPurpose::Authentication, SystemTime::now());
+    println!("Certificate: {}", validation.verdict);
+    if let Some(reason) = &validation.reason {
+        eprintln!("{}", reason);
+    }
+    Ok(validation.verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_challenge() {
+        assert_eq!(parse_hex("00:ff 1A").unwrap(), [0x00, 0xff, 0x1a]);
+        assert!(parse_hex("abc").is_err());
+        assert_eq!(parse_hex("0g").unwrap_err(), "Invalid hex in challenge: g");
+        // Multi-byte characters used to be sliced in the middle
+        assert_eq!(parse_hex("aé").unwrap_err(), "Invalid hex in challenge: é");
+        assert!(parse_hex("+1").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use pcsc::{Attribute, Card};
+use crate::idreader::chip::{self, Atr};
+use crate::idreader::reader::CardProfile;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn printable(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect()
+}
+
+fn print_atr(atr: &[u8]) {
+    println!("ATR: {}", hex(atr));
+    let parsed = match Atr::parse(atr) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            println!("  {}", err);
+            return;
+        }
+    };
+    println!("  TS: {:02X} ({} convention)", parsed.ts, parsed.convention());
+    println!("  T0: {:02X} ({} historical bytes)", parsed.t0, parsed.historical.len());
+    for (name, value) in &parsed.interface {
+        println!("  {}: {:02X}", name, value);
+    }
+    let protocols: Vec<String> = parsed.protocols.iter().map(|protocol| format!("T={}", protocol)).collect();
+    println!("  Protocols: {}", protocols.join(", "));
+    println!("  Historical bytes: {} \"{}\"", hex(&parsed.historical), printable(&parsed.historical));
+    match (parsed.tck, parsed.tck_valid(atr)) {
+        (Some(tck), Some(true)) => println!("  TCK: {:02X} (valid)", tck),
+        (Some(tck), _) => println!("  TCK: {:02X} (invalid)", tck),
+        (None, _) => println!("  TCK: absent"),
+    }
+}
+
+/// Prints what the chip reports about itself, for diagnosing problem cards. Works on cards this
+/// reader does not know too, those get the ATR and CPLC only.
+pub fn card_info(card: &Card) -> Result<(), String> {
+    let atr = card.get_attribute_owned(Attribute::AtrString).map_err(|err| err.to_string())?;
+    print_atr(&atr);
+    let profile = CardProfile::from_atr(&atr);
+    match profile {
+        Some(profile) => println!("Card profile: {}", profile),
+        None => println!("Card profile: unknown"),
+    }
+
+    // Before any SELECT, some cards only answer GET DATA from the card manager
+    match chip::read_cplc(card) {
+        Ok(Some(cplc)) => {
+            println!("CPLC:");
+            for (name, value) in &cplc.fields {
+                println!("  {: <34} {}", format!("{}:", name), hex(value));
+            }
+        }
+        Ok(None) => println!("CPLC: not supported by the card"),
+        Err(err) => println!("CPLC: {}", err),
+    }
+
+    let reader = match profile {
+        Some(profile) => profile.card_reader(),
+        None => return Ok(()),
+    };
+    let reader = reader.as_ref();
+    let fci = reader.select_aid(card)?;
+    if fci.is_empty() {
+        println!("Application: preselected, no version reported");
+    } else {
+        println!("Application FCI: {}", hex(&fci));
+    }
+
+    println!("Files:");
+    for status in chip::probe_files(reader, card)? {
+        println!("  {} {: <28} {}", hex(status.file), status.name, if status.present { "present" } else { "missing" });
+    }
+    Ok(())
+}
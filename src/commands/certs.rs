@@ -0,0 +1,79 @@
+use std::fs;
+use std::time::SystemTime;
+use pcsc::Card;
+use x509_cert::Certificate;
+use crate::idreader::certificate::{read_certificate, AUTH_CERT_FILE, SIGNING_CERT_FILE};
+use crate::idreader::reader::PersonalId;
+use crate::signature::chain::{self, KeyPurpose, TrustStore, Validation, Verdict};
+use crate::signature;
+
+pub struct TrustOptions<'a> {
+    pub ca_dirs: &'a [String],
+    pub no_bundled: bool,
+    pub crls: &'a [String],
+}
+
+pub fn trust_store(options: &TrustOptions) -> Result<TrustStore, String> {
+    let mut store = TrustStore::new(!options.no_bundled)?;
+    for dir in options.ca_dirs {
+        store.add_ca_dir(dir)?;
+    }
+    for crl in options.crls {
+        store.add_crl_file(crl)?;
+    }
+    if store.certificates.is_empty() {
+        let bundled = if options.no_bundled { "" } else { ", certs/mup-ca.pem was built without any" };
+        eprintln!("Warning: no trusted CA certificates{}, add them with --ca-dir", bundled);
+    }
+    Ok(store)
+}
+
+fn print_validation(label: &str, validation: &Validation) {
+    let leaf = &validation.chain[0];
+    println!("{}: {} ({})", label, validation.verdict, leaf.tbs_certificate.subject);
+    for certificate in &validation.chain[1..] {
+        println!("  issued by {}", certificate.tbs_certificate.subject);
+    }
+    if let Some(reason) = &validation.reason {
+        eprintln!("{}: {}", label, reason);
+    }
+}
+
+fn validate_all(store: &TrustStore, certificates: Vec<(String, Certificate, KeyPurpose)>) -> Verdict {
+    let now = SystemTime::now();
+    let mut worst = Verdict::Valid;
+    for (label, certificate, purpose) in certificates {
+        let validation = store.validate(&certificate, purpose, now);
+        print_validation(&label, &validation);
+        worst = worst.max(validation.verdict);
+    }
+    worst
+}
+
+/// Validates the authentication and signing certificates on the card.
+pub fn validate_card(personal_id: &PersonalId, card: &Card, options: &TrustOptions) -> Result<Verdict, String> {
+    let store = trust_store(options)?;
+    let reader = personal_id.card_reader.as_ref();
+    let mut certificates = vec![];
+    for (label, file, purpose) in [
+        ("Authentication", AUTH_CERT_FILE, KeyPurpose::Authentication),
+        ("Signing", SIGNING_CERT_FILE, KeyPurpose::Signing),
+    ] {
+        let certificate = signature::parse_certificate(&read_certificate(reader, card, file)?)?;
+        certificates.push((label.to_string(), certificate, purpose));
+    }
+    Ok(validate_all(&store, certificates))
+}
+
+/// Validates certificate files, PEM or DER, as qualified signing certificates.
+pub fn validate_files(paths: &[String], options: &TrustOptions) -> Result<Verdict, String> {
+    let store = trust_store(options)?;
+    let mut certificates = vec![];
+    for path in paths {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        let loaded = chain::load_certificates(&bytes).map_err(|err| format!("{}: {}", path, err))?;
+        let certificate = loaded.into_iter().next().ok_or_else(|| format!("{}: no certificate found", path))?;
+        certificates.push((path.clone(), certificate, KeyPurpose::Signing));
+    }
+    Ok(validate_all(&store, certificates))
+}
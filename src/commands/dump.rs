@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+use pcsc::Card;
+use serde::Serialize;
+use crate::idreader::certificate::read_certificate;
+use crate::idreader::chip::{self, ID_FILES, PKI_FILES};
+use crate::idreader::reader::PersonalId;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Classic hex dump: offset, 16 bytes in hex, the same bytes as ASCII
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        out.push_str(&format!("{:08X}  {: <48} {}\n", line * 16, hex.join(" "), ascii));
+    }
+    out
+}
+
+struct Dumped {
+    file: String,
+    name: String,
+    result: Result<(String, usize), String>,
+}
+
+fn write_file(dir: &Path, file: &[u8], name: &str, content: Result<Vec<u8>, String>) -> Dumped {
+    let base = format!("{}-{}", hex(file), name.to_lowercase().replace(' ', "-"));
+    let result = content.and_then(|bytes| {
+        let bin = dir.join(format!("{}.bin", base));
+        fs::write(&bin, &bytes).map_err(|err| format!("{}: {}", bin.display(), err))?;
+        let txt = dir.join(format!("{}.hex", base));
+        fs::write(&txt, hex_dump(&bytes)).map_err(|err| format!("{}: {}", txt.display(), err))?;
+        Ok((base, bytes.len()))
+    });
+    match &result {
+        Ok((_, size)) => println!("{} {: <28} {} bytes", hex(file), name, size),
+        Err(err) => eprintln!("{} {: <28} {}", hex(file), name, err),
+    }
+    Dumped { file: hex(file), name: name.to_string(), result }
+}
+
+/// manifest.json
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Manifest {
+    tool: String,
+    atr: String,
+    profile: String,
+    files: Vec<ManifestFile>,
+}
+
+/// Size and file names of a dumped file, or why it couldn't be read
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ManifestFile {
+    file: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<&Dumped> for ManifestFile {
+    fn from(dumped: &Dumped) -> Self {
+        let mut file = ManifestFile { file: dumped.file.clone(), name: dumped.name.clone(), size: None, binary: None, hex: None, error: None };
+        match &dumped.result {
+            Ok((base, size)) => {
+                file.size = Some(*size);
+                file.binary = Some(format!("{}.bin", base));
+                file.hex = Some(format!("{}.hex", base));
+            }
+            Err(err) => file.error = Some(err.clone()),
+        }
+        file
+    }
+}
+
+fn manifest(personal_id: &PersonalId, dumped: &[Dumped]) -> String {
+    let manifest = Manifest {
+        tool: format!("rsid_reader {}", env!("CARGO_PKG_VERSION")),
+        atr: hex(&personal_id.atr),
+        profile: personal_id.profile.to_string(),
+        files: dumped.iter().map(ManifestFile::from).collect(),
+    };
+    // Strings and numbers always serialize
+    serde_json::to_string_pretty(&manifest).expect("JSON serialization failed") + "\n"
+}
+
+/// Writes every eID file as FILE-name.bin and FILE-name.hex, headers included, and manifest.json.
+/// A file that can't be read is recorded in the manifest instead of stopping the dump.
+pub fn dump(personal_id: &PersonalId, card: &Card, dir: &str) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+
+    let mut dumped = vec![];
+    reader.select_aid(card)?;
+    for (name, file) in ID_FILES {
+        dumped.push(write_file(dir, file, name, chip::read_id_file(reader, card, file)));
+    }
+    for file in chip::discover_id_files(reader, card)? {
+        dumped.push(write_file(dir, &file, "Unknown", chip::read_id_file(reader, card, &file)));
+    }
+    for (name, file) in PKI_FILES {
+        dumped.push(write_file(dir, file, name, read_certificate(reader, card, file)));
+    }
+
+    let path = dir.join("manifest.json");
+    fs::write(&path, manifest(personal_id, &dumped)).map_err(|err| format!("{}: {}", path.display(), err))?;
+    println!("Manifest written to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use pcsc::Card;
+use crate::idreader::chip::{self, ID_FILES, PKI_FILES};
+use crate::idreader::fcp::FileControl;
+use crate::idreader::reader::PersonalId;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_range(range: &str) -> Result<(u16, u16), String> {
+    let parse = |id: &str| u16::from_str_radix(id.trim(), 16).map_err(|_| format!("{} is not a hex file identifier", id));
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (parse(first)?, parse(last)?),
+        None => (parse(range)?, parse(range)?),
+    };
+    if first > last {
+        return Err(format!("Range {} is empty", range));
+    }
+    Ok((first, last))
+}
+
+fn print_control(control: &FileControl) {
+    let size = control.size.map(|size| format!("{} bytes", size)).unwrap_or_else(|| "size unknown".to_string());
+    let life_cycle = control.life_cycle_name().map(|name| format!(", {}", name)).unwrap_or_default();
+    println!("  {}, {}{}", control.kind(), size, life_cycle);
+    if let Some(name) = &control.df_name {
+        println!("  DF name: {}", hex(name));
+    }
+    for (operation, condition) in &control.access {
+        println!("  {: <13} {}", format!("{}:", operation), condition);
+    }
+    for (tag, value) in &control.other {
+        println!("  Tag {:02X}: {}", tag, hex(value));
+    }
+}
+
+/// Lists the files that answer SELECT in `range`, with what their FCP says about them.
+pub fn explore(personal_id: &PersonalId, card: &Card, range: &str, pki: bool) -> Result<(), String> {
+    let (first, last) = parse_range(range)?;
+    let known = if pki { PKI_FILES } else { ID_FILES };
+    let found = chip::explore_files(personal_id.card_reader.as_ref(), card, first, last, pki)?;
+
+    for explored in &found {
+        let name = known.iter().find(|(_, file)| *file == explored.file).map(|(name, _)| *name).unwrap_or("Unknown");
+        println!("{:02X}{:02X} {}", explored.file[0], explored.file[1], name);
+        if explored.response.is_empty() {
+            println!("  No FCP returned");
+            continue;
+        }
+        match &explored.control {
+            Ok(control) => print_control(control),
+            Err(err) => println!("  {}", err),
+        }
+        println!("  Raw: {}", hex(&explored.response));
+    }
+    println!("{} of {} file identifiers exist", found.len(), (last - first) as u32 + 1);
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use crate::idreader::jmbg::Jmbg;
+
+/// Checks a JMBG typed in, e.g. from a form, and prints what it encodes.
+pub fn check(number: &str) -> Result<(), String> {
+    let jmbg = Jmbg::parse(number).map_err(|err| format!("Invalid JMBG: {}", err))?;
+    println!("JMBG {} is valid", number.trim());
+    println!("  Date of birth: {}", jmbg.date_of_birth);
+    println!("  Region:        {:02} ({})", jmbg.region, jmbg.region_name());
+    println!("  Sex:           {} ({:03})", jmbg.sex(), jmbg.serial);
+    println!("  Control digit: {}", jmbg.checksum);
+    Ok(())
+}
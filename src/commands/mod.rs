@@ -0,0 +1,8 @@
+pub mod pin;
+pub mod sign;
+pub mod certs;
+pub mod auth;
+pub mod card_info;
+pub mod dump;
+pub mod explore;
+pub mod jmbg;
@@ -0,0 +1,118 @@
+use std::io::{self, BufRead, Write};
+use pcsc::Card;
+use zeroize::Zeroizing;
+use crate::idreader::pin::{self, PinStatus};
+use crate::idreader::reader::PersonalId;
+
+/// Reads a secret without echoing it. The returned buffer is wiped when dropped.
+pub fn read_secret(prompt: &str) -> Result<Zeroizing<String>, String> {
+    rpassword::prompt_password(prompt).map(Zeroizing::new).map_err(|err| err.to_string())
+}
+
+/// Asks the new PIN twice and checks its format before anything is sent to the card.
+fn read_new_pin() -> Result<Zeroizing<String>, String> {
+    let new_pin = read_secret("New PIN: ")?;
+    pin::validate_pin(&new_pin)?;
+    let confirmation = read_secret("Repeat new PIN: ")?;
+    if *new_pin != *confirmation {
+        return Err("PIN entries do not match, nothing was changed".to_string());
+    }
+    Ok(new_pin)
+}
+
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().map_err(|err| err.to_string())?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).map_err(|err| err.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "da"))
+}
+
+pub fn status(personal_id: &PersonalId, card: &Card) -> Result<(), String> {
+    let status = pin::pin_status(personal_id.card_reader.as_ref(), card)?;
+    match status {
+        PinStatus::Verified => println!("PIN already verified in this session"),
+        status => println!("{}", status),
+    }
+    Ok(())
+}
+
+/// Asks for the PIN and verifies it, as required before any private key operation.
+pub fn login(personal_id: &PersonalId, card: &Card) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    match pin::pin_status(reader, card)? {
+        PinStatus::Blocked => return Err(PinStatus::Blocked.to_string()),
+        PinStatus::Remaining(1) => eprintln!("Warning: only 1 attempt left, a wrong PIN will block the card"),
+        _ => {}
+    }
+
+    let pin = read_secret("PIN: ")?;
+    match pin::verify_pin(reader, card, &pin)? {
+        PinStatus::Verified => Ok(()),
+        status => Err(status.to_string()),
+    }
+}
+
+pub fn verify(personal_id: &PersonalId, card: &Card) -> Result<(), String> {
+    login(personal_id, card)?;
+    println!("{}", PinStatus::Verified);
+    Ok(())
+}
+
+pub fn change(personal_id: &PersonalId, card: &Card) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    match pin::pin_status(reader, card)? {
+        PinStatus::Blocked => return Err(format!("{}, use `pin unblock`", PinStatus::Blocked)),
+        PinStatus::Remaining(1) => {
+            eprintln!("Warning: only 1 attempt left, a wrong current PIN will block the card");
+            if !confirm("Continue?")? {
+                return Ok(());
+            }
+        }
+        _ => {}
+    }
+
+    let old_pin = read_secret("Current PIN: ")?;
+    pin::validate_pin(&old_pin)?;
+    let new_pin = read_new_pin()?;
+    if *old_pin == *new_pin {
+        return Err("New PIN must differ from the current one".to_string());
+    }
+
+    match pin::change_pin(reader, card, &old_pin, &new_pin)? {
+        PinStatus::Verified => {
+            println!("PIN changed");
+            Ok(())
+        }
+        status => Err(format!("PIN not changed: {}", status)),
+    }
+}
+
+pub fn unblock(personal_id: &PersonalId, card: &Card) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    match pin::puk_status(reader, card)? {
+        PinStatus::Blocked => return Err("PUK is blocked, the card can no longer be unblocked".to_string()),
+        PinStatus::Remaining(1) => {
+            eprintln!("Warning: only 1 PUK attempt left, a wrong PUK will permanently block the card");
+            if !confirm("Continue?")? {
+                return Ok(());
+            }
+        }
+        PinStatus::Remaining(n) => eprintln!("{} PUK attempts left", n),
+        PinStatus::Verified => {}
+    }
+
+    let puk = read_secret("PUK: ")?;
+    pin::validate_puk(&puk)?;
+    let new_pin = read_new_pin()?;
+
+    match pin::unblock_pin(reader, card, &puk, &new_pin)? {
+        PinStatus::Verified => {
+            println!("PIN unblocked and set to the new value");
+            Ok(())
+        }
+        PinStatus::Remaining(1) => Err("Wrong PUK, 1 attempt left before the card is permanently blocked".to_string()),
+        PinStatus::Remaining(n) => Err(format!("Wrong PUK, {} attempts left", n)),
+        PinStatus::Blocked => Err("PUK is blocked, the card can no longer be unblocked".to_string()),
+    }
+}
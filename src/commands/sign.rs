@@ -0,0 +1,92 @@
+use std::fs::{self, File};
+use std::io;
+use pcsc::Card;
+use sha2::{Digest, Sha256};
+use crate::idreader::certificate::{read_certificate, SIGNING_CERT_FILE};
+use crate::idreader::reader::PersonalId;
+use crate::idreader::sign::{self, SIGNING_KEY_REFERENCE};
+use crate::signature::{self, cms, pades, xades};
+use super::pin::login;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SignatureFormat {
+    /// CMS/PKCS#7 SignedData with the signer certificate (.p7s)
+    Cms,
+    /// Bare RSA PKCS#1 v1.5 signature (.sig)
+    Raw,
+}
+
+pub fn sha256_file(path: &str) -> Result<[u8; 32], String> {
+    let mut file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|err| format!("{}: {}", path, err))?;
+    Ok(hasher.finalize().into())
+}
+
+/// Writes a detached signature over the SHA-256 of the file.
+pub fn sign(personal_id: &PersonalId, card: &Card, path: &str, format: SignatureFormat, output: Option<&str>) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    let digest = sha256_file(path)?;
+
+    // Read the certificate before asking for the PIN so a missing one fails early
+    let certificate = match format {
+        SignatureFormat::Cms => Some(signature::parse_certificate(&read_certificate(reader, card, SIGNING_CERT_FILE)?)?),
+        SignatureFormat::Raw => None,
+    };
+
+    login(personal_id, card)?;
+    let (signature, extension) = match certificate {
+        Some(certificate) => {
+            let sign_on_card = |hash: &[u8; 32]| sign::sign_digest(reader, card, SIGNING_KEY_REFERENCE, hash);
+            let attributes = vec![cms::signing_time_attribute()?, cms::signing_certificate_attribute(&certificate)?];
+            (cms::build_detached(&certificate, &digest, attributes, sign_on_card)?, "p7s")
+        }
+        None => (sign::sign_digest(reader, card, SIGNING_KEY_REFERENCE, &digest)?, "sig"),
+    };
+
+    let output = output.map(str::to_string).unwrap_or_else(|| format!("{}.{}", path, extension));
+    fs::write(&output, signature).map_err(|err| format!("{}: {}", output, err))?;
+    println!("Signature written to {}", output);
+    Ok(())
+}
+
+/// Writes FILE with an enveloped XAdES-BES signature to FILE.signed.xml or `output`.
+pub fn sign_xml(personal_id: &PersonalId, card: &Card, path: &str, output: Option<&str>) -> Result<(), String> {
+    let reader = personal_id.card_reader.as_ref();
+    let xml = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let certificate = signature::parse_certificate(&read_certificate(reader, card, SIGNING_CERT_FILE)?)?;
+
+    login(personal_id, card)?;
+    let sign_on_card = |hash: &[u8; 32]| sign::sign_digest(reader, card, SIGNING_KEY_REFERENCE, hash);
+    let signed = xades::sign(&xml, &certificate, sign_on_card)?;
+
+    let output = output.map(str::to_string).unwrap_or_else(|| {
+        format!("{}.signed.xml", path.strip_suffix(".xml").unwrap_or(path))
+    });
+    fs::write(&output, signed).map_err(|err| format!("{}: {}", output, err))?;
+    println!("Signed XML written to {}", output);
+    Ok(())
+}
+
+/// Signs a rendered PDF printout with the key of the card in `card`,
+/// which may be the card holder's or an operator's card.
+pub fn sign_pdf(signer: &PersonalId, card: &Card, pdf: &[u8]) -> Result<Vec<u8>, String> {
+    let reader = signer.card_reader.as_ref();
+    let certificate = signature::parse_certificate(&read_certificate(reader, card, SIGNING_CERT_FILE)?)?;
+
+    login(signer, card)?;
+    let sign_on_card = |hash: &[u8; 32]| sign::sign_digest(reader, card, SIGNING_KEY_REFERENCE, hash);
+    pades::sign(pdf, &certificate, "ID card data printout", sign_on_card)
+}
+
+/// Checks a detached CMS signature without a card present.
+pub fn verify(path: &str, signature_path: Option<&str>) -> Result<(), String> {
+    let signature_path = signature_path.map(str::to_string).unwrap_or_else(|| format!("{}.p7s", path));
+    let signature = fs::read(&signature_path).map_err(|err| format!("{}: {}", signature_path, err))?;
+    let digest = sha256_file(path)?;
+
+    let signer = cms::verify_detached(&signature, &digest)?;
+    println!("Signature valid");
+    println!("Signed by: {}", signer.tbs_certificate.subject);
+    Ok(())
+}
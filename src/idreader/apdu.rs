@@ -0,0 +1,56 @@
+use pcsc::*;
+
+use super::reader::SUCCESS_RESPONSE;
+
+pub struct Response {
+    pub data: Vec<u8>,
+    pub sw: [u8; 2],
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.sw == SUCCESS_RESPONSE
+    }
+}
+
+/// Sends an APDU and splits the reply into data and status word.
+/// Follows `61xx` with GET RESPONSE and repeats the command with the right Le on `6Cxx`,
+/// so callers always see the full data. `has_le` tells that the last byte of `apdu` is Le (case 2
+/// and 4 commands), commands without it are never repeated.
+pub fn transmit(card: &Card, apdu: &[u8], has_le: bool) -> Result<Response, String> {
+    let mut rapdu_buf = [0; MAX_BUFFER_SIZE];
+    let rapdu = match card.transmit(apdu, &mut rapdu_buf) {
+        Ok(rapdu) => rapdu,
+        Err(err) => {
+            return Err(err.to_string());
+        }
+    };
+    if rapdu.len() < 2 {
+        return Err("Reader returned a truncated response".to_string());
+    }
+    let mut data = rapdu[..rapdu.len() - 2].to_vec();
+    let mut sw = [rapdu[rapdu.len() - 2], rapdu[rapdu.len() - 1]];
+
+    if sw[0] == 0x6c && has_le && apdu.last() != Some(&sw[1]) {
+        let mut retry = apdu.to_vec();
+        *retry.last_mut().unwrap() = sw[1];
+        return transmit(card, &retry, has_le);
+    }
+
+    while sw[0] == 0x61 {
+        let get_response = [0x00, 0xc0, 0x00, 0x00, sw[1]];
+        let rapdu = match card.transmit(&get_response, &mut rapdu_buf) {
+            Ok(rapdu) => rapdu,
+            Err(err) => {
+                return Err(err.to_string());
+            }
+        };
+        if rapdu.len() < 2 {
+            return Err("Reader returned a truncated response".to_string());
+        }
+        data.extend_from_slice(&rapdu[..rapdu.len() - 2]);
+        sw = [rapdu[rapdu.len() - 2], rapdu[rapdu.len() - 1]];
+    }
+
+    Ok(Response { data, sw })
+}
@@ -0,0 +1,62 @@
+use pcsc::*;
+use rsa::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use x509_cert::Certificate;
+
+use super::apdu::transmit;
+use super::certificate::{read_certificate, AUTH_CERT_FILE};
+use super::reader::CardReader;
+use super::sign::{self, sha256_digest_info, AUTHENTICATION_TEMPLATE, AUTH_KEY_REFERENCE};
+use crate::signature;
+
+pub const CHALLENGE_LENGTH: usize = 32;
+
+/// What the card returns for a challenge, to be checked with [`verify`].
+pub struct CardResponse {
+    /// DER of the authentication certificate, read in the same session as the signature
+    pub certificate: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// A fresh random challenge. Verifiers should never reuse one.
+pub fn new_challenge() -> [u8; CHALLENGE_LENGTH] {
+    let mut challenge = [0; CHALLENGE_LENGTH];
+    OsRng.fill_bytes(&mut challenge);
+    challenge
+}
+
+/// INTERNAL AUTHENTICATE over the SHA-256 DigestInfo of the challenge, so the result is an ordinary
+/// RSA PKCS#1 v1.5 signature. Cards without the command sign with the authentication key instead.
+/// The PIN has to be verified in the same session beforehand.
+pub fn internal_authenticate(reader: &dyn CardReader, card: &Card, challenge: &[u8]) -> Result<Vec<u8>, String> {
+    let digest_info = sha256_digest_info(&Sha256::digest(challenge).into());
+
+    reader.select_pki(card)?;
+    sign::set_security_environment(card, AUTHENTICATION_TEMPLATE, AUTH_KEY_REFERENCE)?;
+    let apdu: &[u8] = &[0x00, 0x88, 0x00, 0x00, digest_info.len() as u8];
+    let apdu = [apdu, &digest_info, &[0x00]].concat();
+    let response = transmit(card, &apdu, true)?;
+    match response.sw {
+        [0x90, 0x00] => Ok(response.data),
+        [0x69, 0x82] => Err("Card refused to authenticate, the PIN is not verified".to_string()),
+        [0x6d, 0x00] | [0x6a, 0x81] => sign::sign_digest_info(reader, card, AUTH_KEY_REFERENCE, &digest_info),
+        sw => Err(format!("Reader returned error code at internal_authenticate function {:x?}", sw)),
+    }
+}
+
+/// Reads the authentication certificate and lets the card answer the challenge.
+pub fn respond(reader: &dyn CardReader, card: &Card, challenge: &[u8]) -> Result<CardResponse, String> {
+    let certificate = read_certificate(reader, card, AUTH_CERT_FILE)?;
+    let signature = internal_authenticate(reader, card, challenge)?;
+    Ok(CardResponse { certificate, signature })
+}
+
+/// Checks that the response was made for `challenge` by the key of the certificate in the response.
+/// Whether that certificate is trusted is a separate question, see `signature::chain`.
+pub fn verify(challenge: &[u8], response: &CardResponse) -> Result<Certificate, String> {
+    let certificate = signature::parse_certificate(&response.certificate)?;
+    let digest: [u8; 32] = Sha256::digest(challenge).into();
+    signature::verify_rsa_sha256(&certificate, &digest, &response.signature)
+        .map_err(|_| "Card response does not match the challenge".to_string())?;
+    Ok(certificate)
+}
@@ -0,0 +1,31 @@
+use pcsc::*;
+
+use super::reader::CardReader;
+
+pub const AUTH_CERT_FILE: &[u8] = &[0x0F, 0x10];
+pub const SIGNING_CERT_FILE: &[u8] = &[0x0F, 0x11];
+
+/// Reads a DER encoded X.509 certificate from the PKI application.
+/// Certificate files have no length header, the size is taken from the outer DER SEQUENCE.
+pub fn read_certificate(reader: &dyn CardReader, card: &Card, file: &[u8]) -> Result<Vec<u8>, String> {
+    reader.select_pki(card)?;
+    reader.select_file(card, file, 4)?;
+
+    let header = reader.read_binary(card, 0, 4)?;
+    if header.len() < 4 || header[0] != 0x30 || header[1] != 0x82 {
+        return Err("Certificate file does not contain a DER certificate".to_string());
+    }
+    let len = 4 + (((header[2] as u32) << 8) | header[3] as u32);
+
+    let mut buffer = header;
+    while (buffer.len() as u32) < len {
+        let offset = buffer.len() as u32;
+        let data = reader.read_binary(card, offset, len - offset)?;
+        if data.is_empty() {
+            return Err("Certificate file is shorter than its DER header".to_string());
+        }
+        buffer.extend_from_slice(&data);
+    }
+    buffer.truncate(len as usize);
+    Ok(buffer)
+}
@@ -0,0 +1,225 @@
+use pcsc::*;
+
+use super::apdu::transmit;
+use super::certificate::{AUTH_CERT_FILE, SIGNING_CERT_FILE};
+use super::fcp::FileControl;
+use super::reader::{CardReader, DOCUMENT_FILE, PERSONAL_FILE, PHOTO_FILE, RESIDENCE_FILE};
+
+/// ATR split up as in ISO/IEC 7816-3
+pub struct Atr {
+    pub ts: u8,
+    pub t0: u8,
+    /// Interface bytes in order, named like TA1, TD1, TB2
+    pub interface: Vec<(String, u8)>,
+    /// Protocols offered in TD bytes, T=0 when there are none
+    pub protocols: Vec<u8>,
+    pub historical: Vec<u8>,
+    pub tck: Option<u8>,
+}
+
+impl Atr {
+    pub fn parse(atr: &[u8]) -> Result<Atr, String> {
+        let byte = |index: usize| atr.get(index).copied().ok_or_else(|| "ATR is truncated".to_string());
+        let ts = byte(0)?;
+        let t0 = byte(1)?;
+
+        let mut interface = vec![];
+        let mut protocols = vec![];
+        let mut indicator = t0 >> 4;
+        let mut index = 2;
+        let mut level = 1;
+        loop {
+            let mut next = None;
+            for (bit, name) in [(0x1, 'A'), (0x2, 'B'), (0x4, 'C'), (0x8, 'D')] {
+                if indicator & bit != 0 {
+                    let value = byte(index)?;
+                    interface.push((format!("T{}{}", name, level), value));
+                    index += 1;
+                    if name == 'D' {
+                        next = Some(value);
+                    }
+                }
+            }
+            match next {
+                Some(td) => {
+                    if !protocols.contains(&(td & 0x0f)) {
+                        protocols.push(td & 0x0f);
+                    }
+                    indicator = td >> 4;
+                    level += 1;
+                }
+                None => break,
+            }
+        }
+        if protocols.is_empty() {
+            protocols.push(0);
+        }
+
+        let historical_len = (t0 & 0x0f) as usize;
+        let historical = atr.get(index..index + historical_len).ok_or("ATR is truncated")?.to_vec();
+        index += historical_len;
+        // TCK is only there when a protocol other than T=0 is offered
+        let tck = if protocols.iter().any(|&protocol| protocol != 0) { Some(byte(index)?) } else { None };
+
+        Ok(Atr { ts, t0, interface, protocols, historical, tck })
+    }
+
+    pub fn convention(&self) -> &'static str {
+        match self.ts {
+            0x3b => "direct",
+            0x3f => "inverse",
+            _ => "invalid",
+        }
+    }
+
+    /// XOR of all bytes from T0 up to and including TCK is zero
+    pub fn tck_valid(&self, atr: &[u8]) -> Option<bool> {
+        self.tck.map(|_| atr[1..].iter().fold(0, |acc, b| acc ^ b) == 0)
+    }
+}
+
+/// Field lengths of the 42 byte Card Production Life Cycle data (GlobalPlatform)
+const CPLC_FIELDS: &[(&str, usize)] = &[
+    ("IC fabricator", 2),
+    ("IC type", 2),
+    ("Operating system ID", 2),
+    ("Operating system release date", 2),
+    ("Operating system release level", 2),
+    ("IC fabrication date", 2),
+    ("IC serial number", 4),
+    ("IC batch identifier", 2),
+    ("IC module fabricator", 2),
+    ("IC module packaging date", 2),
+    ("ICC manufacturer", 2),
+    ("IC embedding date", 2),
+    ("IC pre-personalizer", 2),
+    ("IC pre-personalization date", 2),
+    ("IC pre-personalization equipment", 4),
+    ("IC personalizer", 2),
+    ("IC personalization date", 2),
+    ("IC personalization equipment", 4),
+];
+const CPLC_LENGTH: usize = 42;
+
+pub struct Cplc {
+    pub fields: Vec<(&'static str, Vec<u8>)>,
+}
+
+/// GET DATA for the CPLC. `None` when the card does not give it out.
+pub fn read_cplc(card: &Card) -> Result<Option<Cplc>, String> {
+    let response = transmit(card, &[0x80, 0xca, 0x9f, 0x7f, 0x00], true)?;
+    if !response.is_success() {
+        return Ok(None);
+    }
+    let data = response.data.strip_prefix(&[0x9f, 0x7f, CPLC_LENGTH as u8][..]).unwrap_or(&response.data);
+    if data.len() < CPLC_LENGTH {
+        return Err(format!("CPLC data is {} bytes instead of {}", data.len(), CPLC_LENGTH));
+    }
+    let mut fields = vec![];
+    let mut offset = 0;
+    for (name, len) in CPLC_FIELDS {
+        fields.push((*name, data[offset..offset + len].to_vec()));
+        offset += len;
+    }
+    Ok(Some(Cplc { fields }))
+}
+
+/// Files of the ID application, read by `PersonalId::read_id`
+pub const ID_FILES: &[(&str, &[u8])] = &[
+    ("Document", DOCUMENT_FILE),
+    ("Personal", PERSONAL_FILE),
+    ("Residence", RESIDENCE_FILE),
+    ("Photo", PHOTO_FILE),
+];
+
+/// Files of the PKI application
+pub const PKI_FILES: &[(&str, &[u8])] = &[
+    ("Authentication certificate", AUTH_CERT_FILE),
+    ("Signing certificate", SIGNING_CERT_FILE),
+];
+
+pub struct FileStatus {
+    pub name: &'static str,
+    pub file: &'static [u8],
+    pub present: bool,
+}
+
+/// Which of the known files the card has
+pub fn probe_files(reader: &dyn CardReader, card: &Card) -> Result<Vec<FileStatus>, String> {
+    let mut files = vec![];
+    reader.select_aid(card)?;
+    for (name, file) in ID_FILES {
+        files.push(FileStatus { name, file, present: reader.select_file(card, file, 4).is_ok() });
+    }
+    reader.select_pki(card)?;
+    for (name, file) in PKI_FILES {
+        files.push(FileStatus { name, file, present: reader.select_file(card, file, 4).is_ok() });
+    }
+    Ok(files)
+}
+
+/// Whole content of a file of the ID application, its header included. The header is 4 bytes
+/// with the length of the rest in bytes 2 and 3 on Gemalto cards, 6 bytes with it in 4 and 5 on Apollo.
+pub fn read_id_file(reader: &dyn CardReader, card: &Card, file: &[u8]) -> Result<Vec<u8>, String> {
+    let (header_size, length_offset) = reader.file_header();
+    reader.select_file(card, file, 4)?;
+    let header = reader.read_binary(card, 0, header_size)?;
+    if header.len() < header_size as usize {
+        return Err("File is shorter than its header".to_string());
+    }
+    let len = header_size + (((header[length_offset + 1] as u32) << 8) | header[length_offset] as u32);
+
+    let mut buffer = header;
+    while (buffer.len() as u32) < len {
+        let offset = buffer.len() as u32;
+        let data = reader.read_binary(card, offset, len - offset)?;
+        if data.is_empty() {
+            return Err("File is shorter than its header says".to_string());
+        }
+        buffer.extend_from_slice(&data);
+    }
+    buffer.truncate(len as usize);
+    Ok(buffer)
+}
+
+/// Files of the ID application in the 0F00-0F1F range other than `ID_FILES`
+pub fn discover_id_files(reader: &dyn CardReader, card: &Card) -> Result<Vec<[u8; 2]>, String> {
+    reader.select_aid(card)?;
+    let mut found = vec![];
+    for id in 0x00..=0x1f {
+        let file = [0x0f, id];
+        if ID_FILES.iter().any(|(_, known)| *known == file) {
+            continue;
+        }
+        if reader.select_file(card, &file, 4).is_ok() {
+            found.push(file);
+        }
+    }
+    Ok(found)
+}
+
+pub struct ExploredFile {
+    pub file: [u8; 2],
+    /// What SELECT answered
+    pub response: Vec<u8>,
+    pub control: Result<FileControl, String>,
+}
+
+/// SELECTs every file identifier from `first` to `last` in the ID application, or the PKI one,
+/// and keeps the files that exist with their parsed FCP.
+pub fn explore_files(reader: &dyn CardReader, card: &Card, first: u16, last: u16, pki: bool) -> Result<Vec<ExploredFile>, String> {
+    if pki {
+        reader.select_pki(card)?;
+    } else {
+        reader.select_aid(card)?;
+    }
+    let mut found = vec![];
+    for id in first..=last {
+        let file = id.to_be_bytes();
+        if let Ok(response) = reader.select_file(card, &file, 0) {
+            let control = FileControl::parse(&response);
+            found.push(ExploredFile { file, response, control });
+        }
+    }
+    Ok(found)
+}
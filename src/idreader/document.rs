@@ -0,0 +1,428 @@
+use std::fmt;
+use chrono::{Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::jmbg::Jmbg;
+use super::json::base64_bytes;
+use super::labels::{self, Label, Language};
+use super::photo::{self, PhotoOptions};
+use super::reader::{PersonalId, PersonalIdTag, ID_TAGS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+impl Sex {
+    /// The card writes M or F, older cards the Serbian letters
+    pub fn parse(value: &str) -> Option<Sex> {
+        match value.trim() {
+            "M" | "М" => Some(Sex::Male),
+            "F" | "Ž" | "Ж" => Some(Sex::Female),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Sex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sex::Male => write!(f, "M"),
+            Sex::Female => write!(f, "F"),
+        }
+    }
+}
+
+/// Fields holding a date
+pub const DATE_TAGS: &[PersonalIdTag] = &[
+    PersonalIdTag::DateOfBirth,
+    PersonalIdTag::IssuingDate,
+    PersonalIdTag::ExpiryDate,
+    PersonalIdTag::AddressDate,
+];
+
+/// Card dates are DDMMYYYY, some cards write DD.MM.YYYY, with or without the final dot
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    let value = value.trim();
+    let trimmed = value.strip_suffix('.').unwrap_or(value);
+    let pattern = if trimmed.contains('.') { "%d.%m.%Y" } else { "%d%m%Y" };
+    let date = NaiveDate::parse_from_str(trimmed, pattern).map_err(|_| format!("\"{}\" is not a date", value))?;
+    if !(1900..=2100).contains(&date.year()) {
+        return Err(format!("\"{}\" has an implausible year", value));
+    }
+    Ok(date)
+}
+
+/// A value the card holds that could not be parsed into its type
+#[derive(Serialize, Deserialize)]
+pub struct FieldError {
+    pub tag: PersonalIdTag,
+    pub value: String,
+    pub reason: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", tag_name(self.tag), self.reason)
+    }
+}
+
+fn tag_name(tag: PersonalIdTag) -> &'static str {
+    ID_TAGS.iter().find(|id_tag| id_tag.id == tag).map(|id_tag| id_tag.json_id).unwrap_or("")
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Person {
+    pub surname: String,
+    pub given_name: String,
+    pub parent_given_name: String,
+    /// JMBG
+    pub personal_number: String,
+    pub sex: Option<Sex>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub place_of_birth: String,
+    pub community_of_birth: String,
+    pub state_of_birth: String,
+}
+
+impl Person {
+    /// The personal number decoded, `Err` when it is malformed or its control digit is wrong
+    pub fn jmbg(&self) -> Result<Jmbg, String> {
+        Jmbg::parse(&self.personal_number)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    pub number: String,
+    pub issuing_authority: String,
+    pub issuing_date: Option<NaiveDate>,
+    pub expiry_date: Option<NaiveDate>,
+}
+
+/// Whether the document can still be accepted, from its expiry date
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Validity {
+    Valid { days_left: i64 },
+    /// Expires within the warning period
+    Expiring { days_left: i64 },
+    Expired { days_ago: i64 },
+    /// No readable expiry date
+    Unknown,
+}
+
+impl Validity {
+    /// Short name used in JSON
+    pub fn status(&self) -> &'static str {
+        match self {
+            Validity::Valid { .. } => "valid",
+            Validity::Expiring { .. } => "expiring",
+            Validity::Expired { .. } => "expired",
+            Validity::Unknown => "unknown",
+        }
+    }
+
+    /// Days until expiry, negative once expired
+    pub fn days(&self) -> Option<i64> {
+        match self {
+            Validity::Valid { days_left } | Validity::Expiring { days_left } => Some(*days_left),
+            Validity::Expired { days_ago } => Some(-days_ago),
+            Validity::Unknown => None,
+        }
+    }
+}
+
+impl Document {
+    /// The document is valid through its expiry date. Within `warning_days` of it, it is `Expiring`.
+    pub fn validity(&self, today: NaiveDate, warning_days: i64) -> Validity {
+        let expiry = match self.expiry_date {
+            Some(expiry) => expiry,
+            None => return Validity::Unknown,
+        };
+        let days_left = (expiry - today).num_days();
+        if days_left < 0 {
+            Validity::Expired { days_ago: -days_left }
+        } else if days_left <= warning_days {
+            Validity::Expiring { days_left }
+        } else {
+            Validity::Valid { days_left }
+        }
+    }
+
+    /// `validity` as of the local date
+    pub fn validity_today(&self, warning_days: i64) -> Validity {
+        self.validity(Local::now().date_naive(), warning_days)
+    }
+}
+
+/// Parts a card leaves out, e.g. the street in a village, are `None`
+#[derive(Serialize, Deserialize)]
+pub struct Address {
+    pub state: String,
+    pub community: String,
+    pub place: String,
+    pub street: Option<String>,
+    pub house_number: Option<String>,
+    pub house_letter: Option<String>,
+    pub entrance: Option<String>,
+    pub floor: Option<String>,
+    pub apartment_number: Option<String>,
+    /// Date the address was registered
+    pub address_date: Option<NaiveDate>,
+    /// The card has a street but masking hid it, so the place does not take its spot
+    #[serde(skip)]
+    pub street_masked: bool,
+}
+
+impl Address {
+    /// Postal form: street and number with entrance, floor and apartment, then place and municipality,
+    /// then country. Missing parts are left out, a missing house number is written bb (bez broja).
+    /// `value` is applied to the card values, e.g. to transliterate them, but not to the words around them.
+    pub fn lines_with(&self, language: Language, value: impl Fn(&str) -> String) -> Vec<String> {
+        let word = |label| labels::text(label, language);
+        let number = match &self.house_number {
+            Some(number) => format!("{}{}", value(number), self.house_letter.as_deref().map(&value).unwrap_or_default()),
+            None => word(Label::NoHouseNumber).to_string(),
+        };
+        // Villages have no streets, the place takes the street's spot
+        let place_as_street = self.street.is_none() && !self.street_masked;
+        let street = if place_as_street { Some(self.place.as_str()) } else { self.street.as_deref() };
+        let mut first = vec![];
+        if let Some(street) = street.filter(|street| !street.is_empty()) {
+            first.push(format!("{} {}", value(street), number));
+        }
+        for (label, part) in [(Label::EntranceShort, &self.entrance), (Label::FloorShort, &self.floor), (Label::ApartmentShort, &self.apartment_number)] {
+            if let Some(part) = part {
+                first.push(format!("{} {}", word(label), value(part)));
+            }
+        }
+
+        let mut second = vec![];
+        if !place_as_street && !self.place.is_empty() {
+            second.push(value(&self.place));
+        }
+        if !self.community.is_empty() && self.community != self.place {
+            second.push(value(&self.community));
+        }
+
+        [first.join(", "), second.join(", "), value(&self.state)].into_iter().filter(|line| !line.is_empty()).collect()
+    }
+
+    pub fn lines(&self, language: Language) -> Vec<String> {
+        self.lines_with(language, str::to_string)
+    }
+
+    pub fn single_line(&self, language: Language) -> String {
+        self.lines(language).join(", ")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Photo {
+    /// JPEG as stored on the card, base64 encoded when serialized
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl Photo {
+    /// The photo converted, cropped or scaled as `options` say, without metadata
+    pub fn export(&self, options: &PhotoOptions) -> Result<Vec<u8>, String> {
+        photo::convert(&self.data, options)
+    }
+}
+
+/// Everything read from the ID application, grouped and typed.
+/// The values as the card stores them stay available through `PersonalId::value`.
+#[derive(Serialize, Deserialize)]
+pub struct IdentityDocument {
+    pub person: Person,
+    pub document: Document,
+    pub address: Address,
+    pub photo: Photo,
+    /// Values left out above because they don't parse, e.g. a malformed date
+    pub errors: Vec<FieldError>,
+}
+
+impl IdentityDocument {
+    pub fn new(personal_id: &PersonalId) -> IdentityDocument {
+        let text = |tag| personal_id.value(tag).unwrap_or_default().to_string();
+        let optional = |tag| personal_id.value(tag).filter(|value| !value.trim().is_empty()).map(str::to_string);
+        let mut errors = vec![];
+        let mut date = |tag| {
+            let value = personal_id.value(tag)?;
+            parse_date(value).map_err(|reason| errors.push(FieldError { tag, value: value.to_string(), reason })).ok()
+        };
+
+        let mut document = IdentityDocument {
+            person: Person {
+                surname: text(PersonalIdTag::Surname),
+                given_name: text(PersonalIdTag::GivenName),
+                parent_given_name: text(PersonalIdTag::ParentGivenName),
+                personal_number: text(PersonalIdTag::PersonalNumber),
+                sex: personal_id.value(PersonalIdTag::Sex).and_then(Sex::parse),
+                date_of_birth: date(PersonalIdTag::DateOfBirth),
+                place_of_birth: text(PersonalIdTag::PlaceOfBirth),
+                community_of_birth: text(PersonalIdTag::CommunityOfBirth),
+                state_of_birth: text(PersonalIdTag::StateOfBirth),
+            },
+            document: Document {
+                number: text(PersonalIdTag::DocRegNo),
+                issuing_authority: text(PersonalIdTag::IssuingAuthority),
+                issuing_date: date(PersonalIdTag::IssuingDate),
+                expiry_date: date(PersonalIdTag::ExpiryDate),
+            },
+            address: Address {
+                state: text(PersonalIdTag::State),
+                community: text(PersonalIdTag::Community),
+                place: text(PersonalIdTag::Place),
+                street: optional(PersonalIdTag::Street),
+                house_number: optional(PersonalIdTag::HouseNumber),
+                house_letter: optional(PersonalIdTag::HouseLetter),
+                entrance: optional(PersonalIdTag::Entrance),
+                floor: optional(PersonalIdTag::Floor),
+                apartment_number: optional(PersonalIdTag::AppartmentNumber),
+                address_date: date(PersonalIdTag::AddressDate),
+                street_masked: false,
+            },
+            photo: Photo { data: personal_id.image.clone() },
+            errors: vec![],
+        };
+        document.errors = errors;
+        let error = |tag, reason: String| FieldError { tag, value: personal_id.value(tag).unwrap_or_default().to_string(), reason };
+        if let (Some(issued), Some(expires)) = (document.document.issuing_date, document.document.expiry_date) {
+            if expires < issued {
+                document.errors.push(error(PersonalIdTag::ExpiryDate, "expires before it was issued".to_string()));
+            }
+        }
+        if !document.person.personal_number.is_empty() {
+            match document.person.jmbg() {
+                Err(reason) => document.errors.push(error(PersonalIdTag::PersonalNumber, reason)),
+                Ok(jmbg) => {
+                    if document.person.date_of_birth.is_some_and(|date| date != jmbg.date_of_birth) {
+                        document.errors.push(error(PersonalIdTag::DateOfBirth, format!("JMBG says {}", jmbg.date_of_birth)));
+                    }
+                    if document.person.sex.is_some_and(|sex| sex != jmbg.sex()) {
+                        document.errors.push(error(PersonalIdTag::Sex, format!("JMBG says {}", jmbg.sex())));
+                    }
+                }
+            }
+        }
+        document
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_card_dates() {
+        let date = NaiveDate::from_ymd_opt(1990, 1, 31).unwrap();
+        assert_eq!(parse_date("31011990"), Ok(date));
+        assert_eq!(parse_date("31.01.1990"), Ok(date));
+        assert_eq!(parse_date("31.01.1990."), Ok(date));
+        assert_eq!(parse_date(" 31.01.1990. "), Ok(date));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert!(parse_date("").is_err());
+        assert!(parse_date("31021990").is_err());
+        assert!(parse_date("1990-01-31").is_err());
+        assert!(parse_date("3101199").is_err());
+    }
+
+    #[test]
+    fn rejects_implausible_years() {
+        assert!(parse_date("01011899").is_err());
+        assert!(parse_date("01012101").is_err());
+        assert!(parse_date("01011900").is_ok());
+    }
+
+    fn expiring(expiry_date: Option<NaiveDate>) -> Document {
+        Document { number: String::new(), issuing_authority: String::new(), issuing_date: None, expiry_date }
+    }
+
+    #[test]
+    fn validity_at_the_boundaries() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let in_days = |days| expiring(Some(today + chrono::Duration::days(days)));
+
+        assert_eq!(in_days(0).validity(today, 30), Validity::Expiring { days_left: 0 });
+        assert_eq!(in_days(-1).validity(today, 30), Validity::Expired { days_ago: 1 });
+        assert_eq!(in_days(30).validity(today, 30), Validity::Expiring { days_left: 30 });
+        assert_eq!(in_days(31).validity(today, 30), Validity::Valid { days_left: 31 });
+        assert_eq!(in_days(0).validity(today, 0), Validity::Expiring { days_left: 0 });
+        assert_eq!(in_days(1).validity(today, 0), Validity::Valid { days_left: 1 });
+        assert_eq!(expiring(None).validity(today, 30), Validity::Unknown);
+    }
+
+    #[test]
+    fn validity_days() {
+        assert_eq!(Validity::Expired { days_ago: 1 }.days(), Some(-1));
+        assert_eq!(Validity::Expiring { days_left: 0 }.days(), Some(0));
+        assert_eq!(Validity::Unknown.days(), None);
+        assert_eq!(Validity::Unknown.status(), "unknown");
+    }
+
+    fn address(street: Option<&str>, house_number: Option<&str>, place: &str, community: &str) -> Address {
+        Address {
+            state: "СРБ".to_string(),
+            community: community.to_string(),
+            place: place.to_string(),
+            street: street.map(str::to_string),
+            house_number: house_number.map(str::to_string),
+            house_letter: None,
+            entrance: None,
+            floor: None,
+            apartment_number: None,
+            address_date: None,
+            street_masked: false,
+        }
+    }
+
+    #[test]
+    fn village_without_street_or_number() {
+        let village = address(None, None, "Врбица", "Чока");
+        assert_eq!(village.lines(Language::SerbianCyrillic), ["Врбица бб", "Чока", "СРБ"]);
+        assert_eq!(village.lines(Language::SerbianLatin), ["Врбица bb", "Чока", "СРБ"]);
+    }
+
+    #[test]
+    fn entrance_floor_and_apartment() {
+        let mut flat = address(Some("Кнеза Милоша"), Some("12"), "Београд", "Стари град");
+        flat.house_letter = Some("а".to_string());
+        flat.entrance = Some("2".to_string());
+        flat.floor = Some("4".to_string());
+        flat.apartment_number = Some("15".to_string());
+        assert_eq!(flat.lines(Language::SerbianCyrillic), ["Кнеза Милоша 12а, улаз 2, спрат 4, стан 15", "Београд, Стари град", "СРБ"]);
+        assert_eq!(flat.single_line(Language::English), "Кнеза Милоша 12а, entrance 2, floor 4, apt. 15, Београд, Стари град, СРБ");
+        let upper = flat.lines_with(Language::SerbianCyrillic, |value| value.to_uppercase());
+        assert_eq!(upper[0], "КНЕЗА МИЛОША 12А, улаз 2, спрат 4, стан 15");
+    }
+
+    #[test]
+    fn community_equal_to_the_place_is_written_once() {
+        let house = address(Some("Булевар ослобођења"), Some("1"), "Нови Сад", "Нови Сад");
+        assert_eq!(house.lines(Language::SerbianCyrillic), ["Булевар ослобођења 1", "Нови Сад", "СРБ"]);
+    }
+
+    #[test]
+    fn empty_parts_are_left_out() {
+        let mut empty = address(None, None, "", "");
+        empty.state = String::new();
+        assert!(empty.lines(Language::SerbianCyrillic).is_empty());
+
+        let no_place = address(Some("Главна"), Some("3"), "", "Чока");
+        assert_eq!(no_place.lines(Language::SerbianCyrillic), ["Главна 3", "Чока", "СРБ"]);
+    }
+
+    #[test]
+    fn masked_street_is_not_replaced_by_the_place() {
+        let mut masked = address(None, Some("12"), "Београд", "Стари град");
+        masked.street_masked = true;
+        masked.floor = Some("4".to_string());
+        assert_eq!(masked.lines(Language::SerbianCyrillic), ["спрат 4", "Београд, Стари град", "СРБ"]);
+    }
+}
@@ -0,0 +1,286 @@
+/// File control parameters a card answers SELECT with, as in ISO/IEC 7816-4
+pub struct FileControl {
+    pub descriptor: Option<u8>,
+    pub file_id: Option<[u8; 2]>,
+    pub df_name: Option<Vec<u8>>,
+    /// Size of the data (tag 80), or the total size (tag 81) when that is all the card gives
+    pub size: Option<u32>,
+    pub life_cycle: Option<u8>,
+    /// Readable access conditions, operation first
+    pub access: Vec<(String, String)>,
+    /// Tags this parser does not interpret
+    pub other: Vec<(u16, Vec<u8>)>,
+}
+
+/// BER-TLV objects at the top level of `data`
+fn parse_ber(data: &[u8]) -> Result<Vec<(u16, Vec<u8>)>, String> {
+    let mut objects = vec![];
+    let mut index = 0;
+    while index < data.len() {
+        // Padding between objects
+        if data[index] == 0x00 || data[index] == 0xff {
+            index += 1;
+            continue;
+        }
+        let mut tag = data[index] as u16;
+        index += 1;
+        if tag & 0x1f == 0x1f {
+            let next = *data.get(index).ok_or("FCP tag is truncated")?;
+            if next & 0x80 != 0 {
+                return Err(format!("FCP tag {:02X}{:02X}.. is longer than two bytes", tag, next));
+            }
+            tag = (tag << 8) | next as u16;
+            index += 1;
+        }
+        let first = *data.get(index).ok_or("FCP length is truncated")?;
+        index += 1;
+        let len = match first {
+            0x81 => {
+                let len = *data.get(index).ok_or("FCP length is truncated")? as usize;
+                index += 1;
+                len
+            }
+            0x82 => {
+                let bytes = data.get(index..index + 2).ok_or("FCP length is truncated")?;
+                index += 2;
+                ((bytes[0] as usize) << 8) | bytes[1] as usize
+            }
+            len if len < 0x80 => len as usize,
+            _ => return Err(format!("FCP length byte {:02X} is not supported", first)),
+        };
+        let value = data.get(index..index + len).ok_or_else(|| format!("FCP tag {:02X} runs past the response", tag))?;
+        objects.push((tag, value.to_vec()));
+        index += len;
+    }
+    Ok(objects)
+}
+
+fn number(bytes: &[u8]) -> Result<u32, String> {
+    if bytes.len() > 4 {
+        return Err(format!("FCP number {} is longer than four bytes", hex(bytes)));
+    }
+    Ok(bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u32))
+}
+
+/// Operations named by the bits of an access mode byte, b7 down to b1
+const EF_OPERATIONS: [&str; 7] = ["DELETE", "TERMINATE", "ACTIVATE", "DEACTIVATE", "WRITE", "UPDATE", "READ"];
+const DF_OPERATIONS: [&str; 7] = ["DELETE", "TERMINATE", "ACTIVATE", "DEACTIVATE", "CREATE DF", "CREATE EF", "DELETE CHILD"];
+
+/// Security condition byte of the compact format
+fn security_condition(byte: u8) -> String {
+    match byte {
+        0x00 => return "always".to_string(),
+        0xff => return "never".to_string(),
+        _ => {}
+    }
+    let mut conditions = vec![];
+    if byte & 0x40 != 0 {
+        conditions.push("secure messaging");
+    }
+    if byte & 0x20 != 0 {
+        conditions.push("external authentication");
+    }
+    if byte & 0x10 != 0 {
+        conditions.push("PIN");
+    }
+    let joined = conditions.join(if byte & 0x80 != 0 { " and " } else { " or " });
+    match byte & 0x0f {
+        0 => joined,
+        environment => format!("{} (SE {})", joined, environment),
+    }
+}
+
+/// Compact security attributes (tag 8C): an access mode byte, then one condition per set bit
+fn compact_access(value: &[u8], is_df: bool) -> Vec<(String, String)> {
+    let mode = match value.first() {
+        Some(mode) => *mode,
+        None => return vec![],
+    };
+    let operations = if is_df { DF_OPERATIONS } else { EF_OPERATIONS };
+    let mut conditions = value[1..].iter();
+    let mut access = vec![];
+    for (bit, operation) in (0..7).rev().zip(operations) {
+        if mode & (1 << bit) != 0 {
+            let condition = conditions.next().map(|&byte| security_condition(byte)).unwrap_or_else(|| "missing".to_string());
+            access.push((operation.to_string(), condition));
+        }
+    }
+    access
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+impl FileControl {
+    /// Parses an FCP (62), FCI (6F) or FMD (64) template, or the bare objects some cards send.
+    pub fn parse(data: &[u8]) -> Result<FileControl, String> {
+        let mut objects = parse_ber(data)?;
+        if let [(0x62 | 0x64 | 0x6f, content)] = objects.as_slice() {
+            objects = parse_ber(content)?;
+        }
+
+        let mut control = FileControl {
+            descriptor: None, file_id: None, df_name: None, size: None, life_cycle: None, access: vec![], other: vec![],
+        };
+        let mut compact = None;
+        for (tag, value) in objects {
+            match tag {
+                0x80 => control.size = Some(number(&value)?),
+                0x81 if control.size.is_none() => control.size = Some(number(&value)?),
+                0x82 => control.descriptor = value.first().copied(),
+                0x83 if value.len() == 2 => control.file_id = Some([value[0], value[1]]),
+                0x84 => control.df_name = Some(value),
+                0x8a => control.life_cycle = value.first().copied(),
+                0x8c => compact = Some(value),
+                0x8b => control.access.push(("rules".to_string(), format!("EF.ARR {}", hex(&value)))),
+                0x86 => control.access.push(("proprietary".to_string(), hex(&value))),
+                0xa1 | 0xab => control.access.push(("expanded".to_string(), hex(&value))),
+                _ => control.other.push((tag, value)),
+            }
+        }
+        if let Some(value) = compact {
+            let access = compact_access(&value, control.is_df());
+            control.access.splice(0..0, access);
+        }
+        Ok(control)
+    }
+
+    pub fn is_df(&self) -> bool {
+        self.descriptor.map(|descriptor| descriptor & 0x38 == 0x38).unwrap_or(false)
+    }
+
+    pub fn kind(&self) -> String {
+        let descriptor = match self.descriptor {
+            Some(descriptor) => descriptor,
+            None => return "unknown".to_string(),
+        };
+        if self.is_df() {
+            return "DF".to_string();
+        }
+        let structure = match descriptor & 0x07 {
+            0x01 => "transparent",
+            0x02 | 0x03 => "linear fixed",
+            0x04 | 0x05 => "linear variable",
+            0x06 | 0x07 => "cyclic",
+            _ => "no structure",
+        };
+        match descriptor & 0x38 {
+            0x00 => format!("EF {}", structure),
+            0x08 => format!("internal EF {}", structure),
+            _ => format!("proprietary EF {:02X}", descriptor),
+        }
+    }
+
+    pub fn life_cycle_name(&self) -> Option<&'static str> {
+        self.life_cycle.map(|status| match status {
+            0x00 => "no information",
+            0x01 => "creation",
+            0x03 => "initialisation",
+            0x04..=0x07 if status & 0x01 == 0x01 => "activated",
+            0x04..=0x07 => "deactivated",
+            0x0c..=0x0f => "terminated",
+            _ => "proprietary",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// EF 0F02, transparent, 300 bytes, activated, DELETE never, UPDATE with PIN, READ always
+    const EF: [u8; 22] = [
+        0x62, 0x14,
+        0x82, 0x01, 0x01,
+        0x83, 0x02, 0x0f, 0x02,
+        0x80, 0x02, 0x01, 0x2c,
+        0x8a, 0x01, 0x05,
+        0x8c, 0x04, 0x43, 0xff, 0x10, 0x00,
+    ];
+
+    fn error(data: &[u8]) -> String {
+        FileControl::parse(data).err().unwrap()
+    }
+
+    #[test]
+    fn elementary_file() {
+        let control = FileControl::parse(&EF).unwrap();
+        assert_eq!(control.kind(), "EF transparent");
+        assert!(!control.is_df());
+        assert_eq!(control.file_id, Some([0x0f, 0x02]));
+        assert_eq!(control.size, Some(300));
+        assert_eq!(control.life_cycle_name(), Some("activated"));
+        assert_eq!(control.access, [
+            ("DELETE".to_string(), "never".to_string()),
+            ("UPDATE".to_string(), "PIN".to_string()),
+            ("READ".to_string(), "always".to_string()),
+        ]);
+        assert!(control.other.is_empty());
+    }
+
+    #[test]
+    fn dedicated_file_in_fci_with_long_form_lengths() {
+        let name = b"SERBIAN-ID";
+        let mut content = vec![0x82, 0x01, 0x38, 0x84, 0x81, name.len() as u8];
+        content.extend_from_slice(name);
+        content.extend_from_slice(&[0x81, 0x82, 0x00, 0x02, 0x10, 0x00]);
+        content.extend_from_slice(&[0x8a, 0x01, 0x04, 0x8c, 0x03, 0x03, 0x90, 0x31]);
+        content.extend_from_slice(&[0x9f, 0x01, 0x01, 0xaa, 0x00, 0x00]);
+        let mut data = vec![0x6f, 0x81, content.len() as u8];
+        data.extend(content);
+
+        let control = FileControl::parse(&data).unwrap();
+        assert_eq!(control.kind(), "DF");
+        assert_eq!(control.df_name.as_deref(), Some(name.as_ref()));
+        assert_eq!(control.size, Some(0x1000));
+        assert_eq!(control.life_cycle_name(), Some("deactivated"));
+        assert_eq!(control.access, [
+            ("CREATE EF".to_string(), "PIN".to_string()),
+            ("DELETE CHILD".to_string(), "external authentication or PIN (SE 1)".to_string()),
+        ]);
+        assert_eq!(control.other, [(0x9f01, vec![0xaa])]);
+    }
+
+    #[test]
+    fn bare_objects_and_padding() {
+        let control = FileControl::parse(&[0x82, 0x01, 0x01, 0x00, 0xff, 0x80, 0x01, 0x10]).unwrap();
+        assert_eq!(control.kind(), "EF transparent");
+        assert_eq!(control.size, Some(0x10));
+    }
+
+    #[test]
+    fn security_conditions() {
+        assert_eq!(security_condition(0x00), "always");
+        assert_eq!(security_condition(0xff), "never");
+        assert_eq!(security_condition(0xd0), "secure messaging and PIN");
+        assert_eq!(security_condition(0x30), "external authentication or PIN");
+        assert_eq!(compact_access(&[0x03, 0x00], false), [
+            ("UPDATE".to_string(), "always".to_string()),
+            ("READ".to_string(), "missing".to_string()),
+        ]);
+        assert!(compact_access(&[], false).is_empty());
+    }
+
+    #[test]
+    fn truncated_responses_are_errors() {
+        for len in 1..EF.len() {
+            assert!(FileControl::parse(&EF[..len]).is_err(), "{} bytes", len);
+        }
+        assert_eq!(error(&[0x9f]), "FCP tag is truncated");
+        assert_eq!(error(&[0x62]), "FCP length is truncated");
+        assert_eq!(error(&[0x62, 0x81]), "FCP length is truncated");
+        assert_eq!(error(&[0x62, 0x82, 0x00]), "FCP length is truncated");
+        assert_eq!(error(&[0x62, 0x82, 0xff, 0xff, 0x00]), "FCP tag 62 runs past the response");
+    }
+
+    #[test]
+    fn malformed_responses_are_errors() {
+        assert_eq!(error(&[0x62, 0x80, 0x00, 0x00]), "FCP length byte 80 is not supported");
+        assert_eq!(error(&[0x62, 0x84, 0, 0, 0, 1, 0]), "FCP length byte 84 is not supported");
+        assert_eq!(error(&[0x9f, 0x81, 0x01, 0x00]), "FCP tag 9F81.. is longer than two bytes");
+        assert_eq!(error(&[0x80, 0x05, 1, 2, 3, 4, 5]), "FCP number 0102030405 is longer than four bytes");
+        // A broken template inside a valid one
+        assert!(FileControl::parse(&[0x62, 0x02, 0x80, 0x05]).is_err());
+    }
+}
@@ -1,27 +1,29 @@
 use pcsc::*;
 use super::reader::*;
+use super::apdu::transmit;
 
-pub const GEMALTO_CARD_TYPE: &'static [u8] = &[
+pub const GEMALTO_CARD_TYPE: &[u8] = &[
     0x3B, 0xFF, 0x94, 0x00, 0x00, 0x81, 0x31, 0x80, 0x43, 0x80, 0x31, 0x80, 0x65, 0xB0, 0x85, 0x02,
     0x01, 0xF3, 0x12, 0x0F, 0xFF, 0x82, 0x90, 0x00, 0x79,
 ];
 
-pub const GEMALTO_NEW_CARD_TYPE: &'static [u8] = &[
+pub const GEMALTO_NEW_CARD_TYPE: &[u8] = &[
     0x3B, 0xF9, 0x96, 0x00, 0x00, 0x80, 0x31, 0xFE, 0x45, 0x53, 0x43, 0x45, 0x37, 0x20, 0x47, 0x43,
     0x4E, 0x33, 0x5E
 ];
 
-pub const GEMALTO_EVEN_NEWER_CARD_TYPE: &'static [u8] = &[
+pub const GEMALTO_EVEN_NEWER_CARD_TYPE: &[u8] = &[
     0x3B, 0x9E, 0x96, 0x80, 0x31, 0xFE, 0x45, 0x53, 0x43, 0x45, 0x20, 0x38, 0x2E, 0x30, 0x2D, 0x43, 0x31,
     0x56, 0x30, 0x0D, 0x0A, 0x6F
 ];
 
-pub const GEMALTO_CARD_TYPE_1: &'static [u8] = &[
+pub const GEMALTO_CARD_TYPE_1: &[u8] = &[
     0x3B, 0x9E, 0x96, 0x80, 0x31, 0xFE, 0x45, 0x53, 0x43, 0x45, 0x20, 0x38, 0x2E, 0x30, 0x2D, 0x43, 0x32,
     0x56, 0x30, 0x0D, 0x0A, 0x6C
 ];
 
 pub const LICNA_KARTA_AID: &[u8] = &[0xF3, 0x81, 0x00, 0x00, 0x02, 0x53, 0x45, 0x52, 0x49, 0x44, 0x01];
+pub const PKI_AID: &[u8] = &[0xA0, 0x00, 0x00, 0x00, 0x63, 0x50, 0x4B, 0x43, 0x53, 0x2D, 0x31, 0x35];
 
 pub struct GemaltoCardReader {
 }
@@ -46,23 +48,26 @@ impl CardReader for GemaltoCardReader  {
         }
         Ok(data.to_vec())
     }
+
+    fn select_pki(&self, card: &Card) -> Result<Vec<u8>, String> {
+        let apdu: &[u8] = &[0x00, 0xa4, 0x04, 0x00, PKI_AID.len() as u8];
+        let apdu = [apdu, PKI_AID].concat();
+
+        let response = transmit(card, &apdu, false)?;
+        if !response.is_success() {
+            return Err(format!("Reader returned error code at select_pki function {:x?}", response.sw));
+        }
+        Ok(response.data)
+    }
     
     fn select_file(&self, card: &Card, file: &[u8], expected_result_size: u8) -> Result<Vec<u8>, String> {
-        let apdu = [0x00, 0xa4, 0x08, 0x00, 2 as u8, file[0], file[1], expected_result_size];
-        let mut rapdu_buf = [0; MAX_BUFFER_SIZE];
-        let rapdu = match card.transmit(&apdu, &mut rapdu_buf) {
-            Ok(rapdu) => rapdu,
-            Err(err) => {
-                return Err(err.to_string());
-            }
-        };
-        let result = &rapdu[rapdu.len() - 2..];
-        let data = &rapdu[..rapdu.len() - 2];
+        let apdu = [0x00, 0xa4, 0x08, 0x00, 2, file[0], file[1], expected_result_size];
+        let response = transmit(card, &apdu, true)?;
     
-        if result != SUCCESS_RESPONSE {
-            return Err(format!("Reader returned error code at select_file function {:x?}", result));
+        if !response.is_success() {
+            return Err(format!("Reader returned error code at select_file function {:x?}", response.sw));
         }
-        Ok(data.to_vec())
+        Ok(response.data)
     }
     
     fn read_binary(&self, card: &Card, offset: u32, length: u32) -> Result<Vec<u8>, String> {
@@ -88,19 +93,19 @@ impl CardReader for GemaltoCardReader  {
     fn read_raw_file(&self, card: &Card, file: &[u8], strip_tag: bool) -> Result<Vec<u8>, String> {
         let mut buffer: Vec<u8> = vec![];
 
-        self.select_file(&card, file, 4)?;
+        self.select_file(card, file, 4)?;
     
         let len: u32 = 4;
         let mut offset = 0;
 
-        let data = self.read_binary(&card, offset, len)?;
+        let data = self.read_binary(card, offset, len)?;
         let mut len = ((data[3] as u32 & 0xff) << 8) + (data[2] as u32 & 0xff);
         offset += data.len() as u32;
 
         while len > 0 {
-            let data = self.read_binary(&card, offset, len)?;
+            let data = self.read_binary(card, offset, len)?;
 
-            buffer.append(&mut data.clone());
+            buffer.extend_from_slice(&data);
             offset += data.len() as u32;
             len -= data.len() as u32;
         }
@@ -0,0 +1,162 @@
+use chrono::NaiveDate;
+
+use super::document::Sex;
+
+/// Unique master citizen number, DDMMYYYRRBBBK
+pub struct Jmbg {
+    pub date_of_birth: NaiveDate,
+    /// Political region of registration at birth, RR
+    pub region: u8,
+    /// BBB, 000-499 for men and 500-999 for women
+    pub serial: u16,
+    pub checksum: u8,
+}
+
+/// Region codes, the first digit alone where a range shares one meaning
+const REGIONS: &[(u8, &str)] = &[
+    (1, "foreigners in Bosnia and Herzegovina"),
+    (2, "foreigners in Montenegro"),
+    (3, "foreigners in Croatia"),
+    (4, "foreigners in Macedonia"),
+    (5, "foreigners in Slovenia"),
+    (6, "foreigners in Central Serbia"),
+    (7, "foreigners in Vojvodina"),
+    (8, "foreigners in Kosovo"),
+    (9, "naturalized citizens"),
+    (10, "Banja Luka"),
+    (11, "Bihać"),
+    (12, "Doboj"),
+    (13, "Goražde"),
+    (14, "Livno"),
+    (15, "Mostar"),
+    (16, "Prijedor"),
+    (17, "Sarajevo"),
+    (18, "Tuzla"),
+    (19, "Zenica"),
+    (21, "Podgorica"),
+    (26, "Nikšić"),
+    (29, "Pljevlja"),
+    (71, "Belgrade"),
+    (72, "Šumadija and Pomoravlje"),
+    (73, "Niš"),
+    (74, "Southern Morava"),
+    (75, "Zaječar"),
+    (76, "Podunavlje"),
+    (77, "Podrinje and Kolubara"),
+    (78, "Kraljevo"),
+    (79, "Užice"),
+    (80, "Novi Sad"),
+    (81, "Sombor"),
+    (82, "Subotica"),
+    (85, "Zrenjanin"),
+    (86, "Pančevo"),
+    (87, "Kikinda"),
+    (88, "Ruma"),
+    (89, "Sremska Mitrovica"),
+    (91, "Priština"),
+    (92, "Kosovska Mitrovica"),
+    (93, "Peć"),
+    (94, "Đakovica"),
+    (95, "Prizren"),
+    (96, "Gnjilane"),
+];
+
+/// Weights 7654327654321, the result 10 or 11 becomes 0
+fn checksum(digits: &[u8]) -> u8 {
+    let sum: u32 = digits[..12].iter().zip([7, 6, 5, 4, 3, 2, 7, 6, 5, 4, 3, 2]).map(|(&digit, weight)| digit as u32 * weight).sum();
+    match 11 - sum % 11 {
+        10 | 11 => 0,
+        digit => digit as u8,
+    }
+}
+
+impl Jmbg {
+    pub fn parse(value: &str) -> Result<Jmbg, String> {
+        let value = value.trim();
+        if value.len() != 13 || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("\"{}\" is not 13 digits", value));
+        }
+        let digits: Vec<u8> = value.bytes().map(|b| b - b'0').collect();
+        let number = |range: std::ops::Range<usize>| digits[range].iter().fold(0u32, |acc, &digit| acc * 10 + digit as u32);
+
+        let expected = checksum(&digits);
+        if digits[12] != expected {
+            return Err(format!("control digit is {}, should be {}", digits[12], expected));
+        }
+        // Three digit year: 800-999 are the 1800s and 1900s, the rest the 2000s
+        let year = number(4..7);
+        let year = if year >= 800 { 1000 + year } else { 2000 + year };
+        let date_of_birth = NaiveDate::from_ymd_opt(year as i32, number(2..4), number(0..2))
+            .ok_or_else(|| format!("{} does not start with a valid date", value))?;
+
+        Ok(Jmbg { date_of_birth, region: number(7..9) as u8, serial: number(9..12) as u16, checksum: digits[12] })
+    }
+
+    pub fn sex(&self) -> Sex {
+        if self.serial < 500 { Sex::Male } else { Sex::Female }
+    }
+
+    pub fn region_name(&self) -> &'static str {
+        if let Some((_, name)) = REGIONS.iter().find(|(code, _)| *code == self.region) {
+            return name;
+        }
+        match self.region / 10 {
+            1 => "Bosnia and Herzegovina",
+            2 => "Montenegro",
+            3 => "Croatia",
+            4 => "Macedonia",
+            5 => "Slovenia",
+            7 => "Central Serbia",
+            8 => "Vojvodina",
+            9 => "Kosovo",
+            _ => "unknown",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_valid_number() {
+        let jmbg = Jmbg::parse("0101990710008").unwrap();
+        assert_eq!(jmbg.date_of_birth, NaiveDate::from_ymd_opt(1990, 1, 1).unwrap());
+        assert_eq!(jmbg.region, 71);
+        assert_eq!(jmbg.region_name(), "Belgrade");
+        assert_eq!(jmbg.serial, 0);
+        assert_eq!(jmbg.sex(), Sex::Male);
+        assert_eq!(jmbg.checksum, 8);
+
+        let jmbg = Jmbg::parse("1503985805127").unwrap();
+        assert_eq!(jmbg.date_of_birth, NaiveDate::from_ymd_opt(1985, 3, 15).unwrap());
+        assert_eq!(jmbg.region_name(), "Novi Sad");
+        assert_eq!(jmbg.sex(), Sex::Female);
+    }
+
+    #[test]
+    fn rejects_a_wrong_control_digit() {
+        let err = Jmbg::parse("0101990710007").err().unwrap();
+        assert_eq!(err, "control digit is 7, should be 8");
+    }
+
+    #[test]
+    fn remainder_ten_or_eleven_gives_zero() {
+        // 11 - sum % 11 is 11 for the first and 10 for the second
+        assert_eq!(Jmbg::parse("0101990710040").unwrap().checksum, 0);
+        assert_eq!(Jmbg::parse("0101990710130").unwrap().checksum, 0);
+        assert!(Jmbg::parse("0101990710131").is_err());
+    }
+
+    #[test]
+    fn rejects_an_invalid_date() {
+        let err = Jmbg::parse("3102990710005").err().unwrap();
+        assert!(err.contains("valid date"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_other_than_thirteen_digits() {
+        assert!(Jmbg::parse("010199071000").is_err());
+        assert!(Jmbg::parse("010199071000A").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use chrono::SecondsFormat;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use super::document::{parse_date, Validity, DATE_TAGS};
+use super::labels::{self, Label, Language};
+use super::output::{DateFormat, OutputOptions};
+use super::reader::{PersonalId, PersonalIdTag, RawTlv, SectionRead, ID_TAGS};
+
+/// Version of the JSON shape. The minor version grows when members are added, the major version when
+/// members are removed, renamed or change their meaning.
+pub const SCHEMA_VERSION: &str = "1.0";
+
+/// Choices only the JSON output has
+#[derive(Clone, Copy, Debug)]
+pub struct JsonOptions {
+    /// Indented, one value per line, rather than all on one line
+    pub pretty: bool,
+    /// Include the photo, base64 encoded, in "Image"
+    pub photo: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self { pretty: true, photo: true }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonValidity {
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "DaysToExpiry")]
+    pub days_to_expiry: Option<i64>,
+}
+
+impl From<Validity> for JsonValidity {
+    fn from(validity: Validity) -> Self {
+        JsonValidity { status: validity.status().to_string(), days_to_expiry: validity.days() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonAddress {
+    /// The lines joined with commas
+    #[serde(rename = "Line")]
+    pub line: String,
+    #[serde(rename = "Lines")]
+    pub lines: Vec<String>,
+}
+
+/// A TLV the reader does not know, text values in "Value", anything else base64 encoded in "Base64"
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonTlv {
+    /// File identifier in hex, e.g. 0F03
+    #[serde(rename = "File")]
+    pub file: String,
+    #[serde(rename = "Tag")]
+    pub tag: u16,
+    #[serde(rename = "Value", default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(rename = "Base64", default, skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+}
+
+impl From<&RawTlv> for JsonTlv {
+    fn from(tlv: &RawTlv) -> Self {
+        let file = tlv.file.iter().map(|b| format!("{:02X}", b)).collect();
+        match std::str::from_utf8(&tlv.value) {
+            Ok(text) => JsonTlv { file, tag: tlv.tag, value: Some(text.to_string()), base64: None },
+            Err(_) => JsonTlv { file, tag: tlv.tag, value: None, base64: Some(base64::encode(&tlv.value)) },
+        }
+    }
+}
+
+/// Whether one of the ID files could be read
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonSection {
+    /// Personal, Residence, Document or Photo
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Read")]
+    pub read: bool,
+    /// Why the file could not be read
+    #[serde(rename = "Error", default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<&SectionRead> for JsonSection {
+    fn from(section: &SectionRead) -> Self {
+        JsonSection { name: section.name.clone(), read: section.error.is_none(), error: section.error.clone() }
+    }
+}
+
+/// Where and when the card was read, and by which version of this program
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonMeta {
+    /// `SCHEMA_VERSION` of the program that wrote the file
+    #[serde(rename = "SchemaVersion")]
+    pub schema_version: String,
+    /// Version of rsid_reader
+    #[serde(rename = "ToolVersion")]
+    pub tool_version: String,
+    /// When the card was read, RFC 3339 in UTC
+    #[serde(rename = "ReadAt")]
+    pub read_at: Option<String>,
+    /// PC/SC reader name
+    #[serde(rename = "Reader")]
+    pub reader: Option<String>,
+    /// Answer to reset in hex
+    #[serde(rename = "Atr")]
+    pub atr: String,
+    /// Card generation told from the ATR, e.g. Gemalto SCE 8.0 C1
+    #[serde(rename = "CardProfile")]
+    pub card_profile: String,
+    /// The ID files in the order they were read
+    #[serde(rename = "Sections")]
+    pub sections: Vec<JsonSection>,
+}
+
+impl JsonMeta {
+    pub fn new(personal_id: &PersonalId) -> JsonMeta {
+        JsonMeta {
+            schema_version: SCHEMA_VERSION.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            read_at: personal_id.read_at.map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            reader: personal_id.reader.clone(),
+            atr: personal_id.atr.iter().map(|b| format!("{:02X}", b)).collect(),
+            card_profile: personal_id.profile.to_string(),
+            sections: personal_id.sections.iter().map(JsonSection::from).collect(),
+        }
+    }
+}
+
+/// The JSON output: `Meta`, then the card fields under their JSON names in alphabetical order,
+/// a date that doesn't parse written as the card has it and explained in "Errors".
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonId {
+    #[serde(rename = "Meta")]
+    pub meta: JsonMeta,
+    /// Card fields by JSON name, e.g. "Surname". Dates are ISO 8601 unless another format was asked for.
+    /// Masked fields are left out.
+    #[serde(flatten, deserialize_with = "string_fields")]
+    #[schemars(schema_with = "fields_schema")]
+    pub fields: BTreeMap<String, Option<String>>,
+    #[serde(rename = "Validity")]
+    pub validity: JsonValidity,
+    /// The address in postal form
+    #[serde(rename = "Address")]
+    pub address: JsonAddress,
+    /// Values that didn't parse or don't agree with each other
+    #[serde(rename = "Errors", alias = "errors")]
+    pub errors: Vec<String>,
+    /// The photo base64 encoded
+    #[serde(rename = "Image", default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// TLVs this reader does not know
+    #[serde(rename = "Unknown", alias = "unknown", default)]
+    pub unknown: Vec<JsonTlv>,
+}
+
+/// Members not otherwise known, keeping only strings and nulls. Other values are members a later minor
+/// version added, which this version can't know the meaning of.
+fn string_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Option<String>>, D::Error> {
+    let members = BTreeMap::<String, Value>::deserialize(deserializer)?;
+    Ok(members.into_iter().filter_map(|(name, value)| match value {
+        Value::String(text) => Some((name, Some(text))),
+        Value::Null => Some((name, None)),
+        _ => None,
+    }).collect())
+}
+
+/// One optional string or null member per field in `ID_TAGS`
+fn fields_schema(generator: &mut SchemaGenerator) -> Schema {
+    let mut object = SchemaObject { instance_type: Some(InstanceType::Object.into()), ..Default::default() };
+    for tag in ID_TAGS.iter().filter(|tag| tag.id != PersonalIdTag::None) {
+        let mut field = generator.subschema_for::<Option<String>>().into_object();
+        field.metadata().description = Some(labels::text(Label::Field(tag.id), Language::English).to_string());
+        object.object().properties.insert(tag.json_id.to_string(), field.into());
+    }
+    object.into()
+}
+
+impl JsonId {
+    /// Dates in ISO 8601 unless `options` say otherwise
+    pub fn new(personal_id: &PersonalId, options: &OutputOptions, json: &JsonOptions) -> JsonId {
+        let date_format = options.date_format(DateFormat::Iso);
+        let mut fields = BTreeMap::new();
+        for item in personal_id.personal.values() {
+            let masked = match options.privacy.apply(item.tag.id, &item.value) {
+                Some(masked) => masked,
+                None => continue,
+            };
+            // A date that doesn't parse stays as the card has it, so reading the file back finds the same error
+            let value = match DATE_TAGS.contains(&item.tag.id).then(|| parse_date(&masked)) {
+                Some(Ok(date)) => date_format.format(date),
+                Some(Err(_)) => masked,
+                None => options.text(&masked),
+            };
+            fields.insert(item.tag.json_id.to_string(), Some(value));
+        }
+
+        let document = options.document(personal_id);
+        let lines = options.serbian_address_lines(&document.address);
+        let photo = json.photo && !document.photo.data.is_empty();
+        JsonId {
+            meta: JsonMeta::new(personal_id),
+            fields,
+            validity: document.document.validity_today(options.expiry_warning_days).into(),
+            address: JsonAddress { line: lines.join(", "), lines },
+            errors: document.errors.iter().map(ToString::to_string).collect(),
+            image: photo.then(|| base64::encode(&document.photo.data)),
+            // Tags this reader does not know may hold anything, so they are left out when masking
+            unknown: if options.privacy.is_empty() { personal_id.unknown().map(JsonTlv::from).collect() } else { vec![] },
+        }
+    }
+
+    pub fn to_json(&self, pretty: bool) -> String {
+        let json = if pretty { serde_json::to_string_pretty(self) } else { serde_json::to_string(self) };
+        // Maps with string keys, strings and numbers always serialize
+        json.expect("JSON serialization failed")
+    }
+
+    /// JSON Schema of the output, as published in schema/rsid_reader.schema.json
+    pub fn schema() -> String {
+        serde_json::to_string_pretty(&schemars::schema_for!(JsonId)).expect("JSON serialization failed")
+    }
+}
+
+/// Bytes as a base64 string, for `#[serde(with = "base64_bytes")]`
+pub mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        base64::decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_id() -> JsonId {
+        JsonId {
+            meta: JsonMeta {
+                schema_version: SCHEMA_VERSION.to_string(),
+                tool_version: "0.1.0".to_string(),
+                read_at: None,
+                reader: None,
+                atr: "3B".to_string(),
+                card_profile: "Apollo".to_string(),
+                sections: vec![],
+            },
+            fields: BTreeMap::from([("Surname".to_string(), Some("Петровић".to_string())), ("DateOfBirth".to_string(), None)]),
+            validity: JsonValidity { status: "unknown".to_string(), days_to_expiry: None },
+            address: JsonAddress { line: String::new(), lines: vec![] },
+            errors: vec![],
+            image: None,
+            unknown: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = json_id();
+        let parsed: JsonId = serde_json::from_str(&json.to_json(false)).unwrap();
+        assert_eq!(parsed, json);
+    }
+
+    #[test]
+    fn members_added_later_are_skipped() {
+        let mut value = serde_json::to_value(json_id()).unwrap();
+        value["Meta"]["SchemaVersion"] = "1.1".into();
+        value["Chip"] = serde_json::json!({ "Serial": "0123" });
+        value["PhotoSize"] = 4096.into();
+        value["Nickname"] = "Pera".into();
+        let parsed: JsonId = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.fields.get("Surname"), Some(&Some("Петровић".to_string())));
+        assert_eq!(parsed.fields.get("DateOfBirth"), Some(&None));
+        assert_eq!(parsed.fields.get("Nickname"), Some(&Some("Pera".to_string())));
+        assert!(!parsed.fields.contains_key("Chip"));
+        assert!(!parsed.fields.contains_key("PhotoSize"));
+    }
+
+    #[test]
+    fn lowercase_names_of_earlier_files_are_read() {
+        let mut value = serde_json::to_value(json_id()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        assert!(object.contains_key("Errors") && object.contains_key("Unknown"));
+        object.remove("Errors");
+        object.remove("Unknown");
+        object.insert("errors".to_string(), serde_json::json!(["DateOfBirth: \"3102\" is not a date"]));
+        object.insert("unknown".to_string(), serde_json::json!([{ "File": "0F03", "Tag": 1600, "Value": "x" }]));
+        let parsed: JsonId = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.errors, ["DateOfBirth: \"3102\" is not a date"]);
+        assert_eq!(parsed.unknown.len(), 1);
+        assert!(!parsed.fields.contains_key("errors") && !parsed.fields.contains_key("unknown"));
+    }
+
+    #[test]
+    fn schema_file_is_up_to_date() {
+        let published = include_str!("../../schema/rsid_reader.schema.json");
+        assert_eq!(JsonId::schema().trim_end(), published.trim_end(), "run rsid_reader json-schema > schema/rsid_reader.schema.json");
+    }
+}
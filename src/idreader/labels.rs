@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use super::document::{Sex, Validity};
+use super::reader::PersonalIdTag;
+use super::script::Script;
+
+/// Language of labels and other text around the card values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    SerbianLatin,
+    SerbianCyrillic,
+    English,
+}
+
+impl Language {
+    /// From LC_ALL, LC_MESSAGES or LANG, `None` for locales without a translation
+    pub fn from_locale() -> Option<Language> {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())?;
+        if locale.starts_with("sr") {
+            let latin = locale.contains("@latin") || locale.contains("Latn");
+            Some(if latin { Language::SerbianLatin } else { Language::SerbianCyrillic })
+        } else if locale.starts_with("en") {
+            Some(Language::English)
+        } else {
+            None
+        }
+    }
+
+    /// The Serbian variant written in `script`
+    pub fn serbian(script: Script) -> Language {
+        match script {
+            Script::Latin => Language::SerbianLatin,
+            Script::Cyrillic => Language::SerbianCyrillic,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Language, String> {
+        match value.to_lowercase().replace('_', "-").as_str() {
+            "sr-latn" | "sr-latin" => Ok(Language::SerbianLatin),
+            "sr-cyrl" | "sr" => Ok(Language::SerbianCyrillic),
+            "en" => Ok(Language::English),
+            _ => Err(format!("{} is not a supported language, use sr-Latn, sr-Cyrl or en", value)),
+        }
+    }
+}
+
+/// Text the outputs put around card values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Label {
+    Field(PersonalIdTag),
+    Title,
+    CitizenData,
+    DocumentData,
+    /// Longer name of the parent's name field on the printout
+    ParentName,
+    /// Place of birth, municipality and country, over two lines
+    BirthPlaceFirstLine,
+    BirthPlaceSecondLine,
+    /// Address, over two lines
+    ResidenceFirstLine,
+    ResidenceSecondLine,
+    IssuedBy,
+    ValidUntil,
+    DocumentStatus,
+    Address,
+    /// Words inside an address
+    EntranceShort,
+    FloorShort,
+    ApartmentShort,
+    NoHouseNumber,
+    Male,
+    Female,
+    /// After a date the card holds that doesn't parse
+    InvalidDate,
+}
+
+/// sr-Latn, sr-Cyrl and en
+type Translations = (&'static str, &'static str, &'static str);
+
+fn field(tag: PersonalIdTag) -> Translations {
+    match tag {
+        PersonalIdTag::DocRegNo => ("Broj dokumenta", "Број документа", "Document number"),
+        PersonalIdTag::IssuingDate => ("Datum izdavanja", "Датум издавања", "Date of issue"),
+        PersonalIdTag::ExpiryDate => ("Datum isteka", "Датум истека", "Date of expiry"),
+        PersonalIdTag::IssuingAuthority => ("Izdato od", "Издато од", "Issued by"),
+        PersonalIdTag::PersonalNumber => ("JMBG", "ЈМБГ", "Personal number"),
+        PersonalIdTag::Surname => ("Prezime", "Презиме", "Surname"),
+        PersonalIdTag::GivenName => ("Ime", "Име", "Given name"),
+        PersonalIdTag::ParentGivenName => ("Ime roditelja", "Име родитеља", "Parent's name"),
+        PersonalIdTag::Sex => ("Pol", "Пол", "Sex"),
+        PersonalIdTag::PlaceOfBirth => ("Mesto rođenja", "Место рођења", "Place of birth"),
+        PersonalIdTag::CommunityOfBirth => ("Opština rođenja", "Општина рођења", "Municipality of birth"),
+        PersonalIdTag::StateOfBirth => ("Država rođenja", "Држава рођења", "Country of birth"),
+        PersonalIdTag::DateOfBirth => ("Datum rođenja", "Датум рођења", "Date of birth"),
+        PersonalIdTag::State => ("Država", "Држава", "Country"),
+        PersonalIdTag::Community => ("Opština", "Општина", "Municipality"),
+        PersonalIdTag::Place => ("Mesto", "Место", "Place"),
+        PersonalIdTag::Street => ("Ulica", "Улица", "Street"),
+        PersonalIdTag::HouseNumber => ("Kućni broj", "Кућни број", "House number"),
+        PersonalIdTag::HouseLetter => ("Kućna oznaka", "Кућна ознака", "House letter"),
+        PersonalIdTag::Entrance => ("Broj ulaza", "Број улаза", "Entrance"),
+        PersonalIdTag::Floor => ("Sprat broj", "Спрат број", "Floor"),
+        PersonalIdTag::AppartmentNumber => ("Broj stana", "Број стана", "Apartment number"),
+        PersonalIdTag::AddressDate => ("Datum adrese", "Датум адресе", "Address since"),
+        PersonalIdTag::None => ("", "", ""),
+    }
+}
+
+fn translations(label: Label) -> Translations {
+    match label {
+        Label::Field(tag) => field(tag),
+        Label::Title => (
+            "ČITAČ ELEKTRONSKE LIČNE KARTE: ŠTAMPA PODATAKA",
+            "ЧИТАЧ ЕЛЕКТРОНСКЕ ЛИЧНЕ КАРТЕ: ШТАМПА ПОДАТАКА",
+            "ELECTRONIC ID CARD READER: DATA PRINTOUT",
+        ),
+        Label::CitizenData => ("Podaci o građaninu", "Подаци о грађанину", "Citizen"),
+        Label::DocumentData => ("Podaci o dokumentu", "Подаци о документу", "Document"),
+        Label::ParentName => ("Ime jednog roditelja", "Име једног родитеља", "Name of a parent"),
+        Label::BirthPlaceFirstLine => ("Mesto rođenja,", "Место рођења,", "Place of birth,"),
+        Label::BirthPlaceSecondLine => ("opština i država", "општина и држава", "municipality, country"),
+        Label::ResidenceFirstLine => ("Prebivalište i", "Пребивалиште и", "Residence and"),
+        Label::ResidenceSecondLine => ("adresa stana", "адреса стана", "address"),
+        Label::IssuedBy => ("Dokument izdaje", "Документ издаје", "Issued by"),
+        Label::ValidUntil => ("Važi do", "Важи до", "Valid until"),
+        Label::DocumentStatus => ("Status dokumenta", "Статус документа", "Document status"),
+        Label::Address => ("Adresa", "Адреса", "Address"),
+        Label::EntranceShort => ("ulaz", "улаз", "entrance"),
+        Label::FloorShort => ("sprat", "спрат", "floor"),
+        Label::ApartmentShort => ("stan", "стан", "apt."),
+        Label::NoHouseNumber => ("bb", "бб", "n/n"),
+        Label::Male => ("muški", "мушки", "male"),
+        Label::Female => ("ženski", "женски", "female"),
+        Label::InvalidDate => ("neispravan datum", "неисправан датум", "invalid date"),
+    }
+}
+
+pub fn text(label: Label, language: Language) -> &'static str {
+    let (latin, cyrillic, english) = translations(label);
+    match language {
+        Language::SerbianLatin => latin,
+        Language::SerbianCyrillic => cyrillic,
+        Language::English => english,
+    }
+}
+
+pub fn sex(sex: Sex, language: Language) -> &'static str {
+    match sex {
+        Sex::Male => text(Label::Male, language),
+        Sex::Female => text(Label::Female, language),
+    }
+}
+
+pub fn validity(validity: Validity, language: Language) -> String {
+    let (latin, cyrillic, english) = match validity {
+        Validity::Valid { .. } => ("važeća".to_string(), "важећа".to_string(), "valid".to_string()),
+        Validity::Expiring { days_left: 0 } => ("ističe danas".to_string(), "истиче данас".to_string(), "expires today".to_string()),
+        Validity::Expiring { days_left: 1 } => ("ističe sutra".to_string(), "истиче сутра".to_string(), "expires tomorrow".to_string()),
+        Validity::Expiring { days_left } => (
+            format!("ističe za {} dana", days_left),
+            format!("истиче за {} дана", days_left),
+            format!("expires in {} days", days_left),
+        ),
+        Validity::Expired { days_ago: 1 } => ("istekla juče".to_string(), "истекла јуче".to_string(), "expired yesterday".to_string()),
+        Validity::Expired { days_ago } => (
+            format!("istekla pre {} dana", days_ago),
+            format!("истекла пре {} дана", days_ago),
+            format!("expired {} days ago", days_ago),
+        ),
+        Validity::Unknown => ("nepoznat".to_string(), "непознат".to_string(), "unknown".to_string()),
+    };
+    match language {
+        Language::SerbianLatin => latin,
+        Language::SerbianCyrillic => cyrillic,
+        Language::English => english,
+    }
+}
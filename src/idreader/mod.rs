@@ -1,3 +1,19 @@
 pub mod reader;
+pub mod apdu;
+pub mod pin;
+pub mod sign;
+pub mod certificate;
+pub mod auth;
+pub mod chip;
+pub mod fcp;
+pub mod document;
+pub mod jmbg;
+pub mod output;
+pub mod script;
+pub mod labels;
+pub mod photo;
+pub mod privacy;
+pub mod json;
 mod gemalto_card_reader;
-mod apollo_card_reader;
\ No newline at end of file
+mod apollo_card_reader;
+mod offline_card_reader;
\ No newline at end of file
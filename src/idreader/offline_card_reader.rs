@@ -0,0 +1,31 @@
+use pcsc::*;
+
+use super::reader::*;
+
+const NO_CARD: &str = "The data was loaded from a file, there is no card to talk to";
+
+/// Stands in for the card when a `PersonalId` is loaded from a saved JSON
+pub struct OfflineCardReader {
+}
+
+impl CardReader for OfflineCardReader {
+    fn select_aid(&self, _card: &Card) -> Result<Vec<u8>, String> {
+        Err(NO_CARD.to_string())
+    }
+
+    fn select_pki(&self, _card: &Card) -> Result<Vec<u8>, String> {
+        Err(NO_CARD.to_string())
+    }
+
+    fn select_file(&self, _card: &Card, _file: &[u8], _expected_result_size: u8) -> Result<Vec<u8>, String> {
+        Err(NO_CARD.to_string())
+    }
+
+    fn read_binary(&self, _card: &Card, _offset: u32, _length: u32) -> Result<Vec<u8>, String> {
+        Err(NO_CARD.to_string())
+    }
+
+    fn read_raw_file(&self, _card: &Card, _file: &[u8], _strip_tag: bool) -> Result<Vec<u8>, String> {
+        Err(NO_CARD.to_string())
+    }
+}
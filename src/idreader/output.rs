@@ -0,0 +1,151 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use chrono::NaiveDate;
+
+use super::document::{Address, IdentityDocument};
+use super::labels::Language;
+use super::privacy::Privacy;
+use super::reader::{PersonalId, PersonalIdTag};
+use super::script::Script;
+
+/// How dates are written in the outputs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// 1990-01-31, ISO 8601
+    Iso,
+    /// 31.01.1990., as written in Serbia
+    Local,
+    /// strftime pattern, e.g. %d/%m/%Y
+    Custom(String),
+}
+
+impl DateFormat {
+    pub fn format(&self, date: NaiveDate) -> String {
+        match self {
+            DateFormat::Iso => date.format("%Y-%m-%d").to_string(),
+            DateFormat::Local => date.format("%d.%m.%Y.").to_string(),
+            DateFormat::Custom(pattern) => date.format(pattern).to_string(),
+        }
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<DateFormat, String> {
+        match value {
+            "iso" => Ok(DateFormat::Iso),
+            "local" => Ok(DateFormat::Local),
+            pattern => {
+                // Fields a date doesn't have, such as %H, only fail when formatting, where `format` would panic
+                let sample = NaiveDate::from_ymd_opt(1990, 1, 31).unwrap();
+                let mut formatted = String::new();
+                match write!(formatted, "{}", sample.format(pattern)) {
+                    Ok(()) => Ok(DateFormat::Custom(pattern.to_string())),
+                    Err(_) => Err(format!("{} is not a valid date format", pattern)),
+                }
+            }
+        }
+    }
+}
+
+pub const DEFAULT_EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// Choices shared by the terminal, JSON and PDF outputs
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    /// Overrides each output's own date format: ISO 8601 for JSON, local for terminal and PDF
+    pub date_format: Option<DateFormat>,
+    /// How many days before expiry a document is reported as expiring
+    pub expiry_warning_days: i64,
+    /// Script to write labels and card values in, `None` to keep them as they are
+    pub script: Option<Script>,
+    /// Language of the labels, `None` for the Serbian variant of `script`, or else each output's own
+    pub language: Option<Language>,
+    /// Values to leave out or show only in part
+    pub privacy: Privacy,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self { date_format: None, expiry_warning_days: DEFAULT_EXPIRY_WARNING_DAYS, script: None, language: None, privacy: Privacy::default() }
+    }
+}
+
+impl OutputOptions {
+    pub fn date_format(&self, default: DateFormat) -> DateFormat {
+        self.date_format.clone().unwrap_or(default)
+    }
+
+    /// The card's values grouped and typed, masked by `privacy`
+    pub fn document(&self, personal_id: &PersonalId) -> IdentityDocument {
+        self.privacy.document(personal_id.document())
+    }
+
+    /// Name for the JSON and PDF files written from the card, see `Privacy::file_stem`
+    pub fn file_stem(&self, personal_id: &PersonalId) -> String {
+        let number = |tag| personal_id.value(tag).unwrap_or_default().trim();
+        self.privacy.file_stem(number(PersonalIdTag::PersonalNumber), number(PersonalIdTag::DocRegNo))
+    }
+
+    pub fn language(&self, default: Language) -> Language {
+        self.language.or_else(|| self.script.map(Language::serbian)).unwrap_or(default)
+    }
+
+    /// Address lines in the chosen script. The words around the values are Serbian in the values' script,
+    /// whatever the Serbian label variant, so one address never mixes scripts.
+    pub fn address_lines(&self, address: &Address, default_language: Language) -> Vec<String> {
+        match self.language(default_language) {
+            Language::English => address.lines_with(Language::English, |value| self.text(value)),
+            _ => self.serbian_address_lines(address),
+        }
+    }
+
+    /// Address lines with Serbian words in the values' script whatever `--lang` says, as JSON has them
+    pub fn serbian_address_lines(&self, address: &Address) -> Vec<String> {
+        address.lines_with(Language::serbian(self.script.unwrap_or(Script::Cyrillic)), |value| self.text(value))
+    }
+
+    /// `text` in the chosen script
+    pub fn text(&self, text: &str) -> String {
+        match self.script {
+            Some(script) => script.convert(text),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(1990, 1, 31).unwrap()
+    }
+
+    #[test]
+    fn named_formats() {
+        assert_eq!("iso".parse::<DateFormat>().unwrap().format(date()), "1990-01-31");
+        assert_eq!("local".parse::<DateFormat>().unwrap().format(date()), "31.01.1990.");
+    }
+
+    #[test]
+    fn custom_format() {
+        let format: DateFormat = "%d/%m/%Y".parse().unwrap();
+        assert_eq!(format, DateFormat::Custom("%d/%m/%Y".to_string()));
+        assert_eq!(format.format(date()), "31/01/1990");
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!("%Q".parse::<DateFormat>().is_err());
+        assert!("%d.%m.%".parse::<DateFormat>().is_err());
+    }
+
+    #[test]
+    fn rejects_time_fields() {
+        assert!("%H:%M".parse::<DateFormat>().is_err());
+        assert!("%Y-%m-%dT%H:%M:%S".parse::<DateFormat>().is_err());
+        assert!("%+".parse::<DateFormat>().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::Cursor;
+use std::str::FromStr;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GenericImageView, ImageOutputFormat};
+
+/// Passport photos are 35 by 45 mm
+const PASSPORT_WIDTH: u32 = 35;
+const PASSPORT_HEIGHT: u32 = 45;
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhotoFormat {
+    Jpeg,
+    Png,
+}
+
+impl PhotoFormat {
+    /// From the extension of `path`, `None` when it is neither JPEG nor PNG
+    pub fn from_path(path: &str) -> Option<PhotoFormat> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for PhotoFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<PhotoFormat, String> {
+        match value.to_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(PhotoFormat::Jpeg),
+            "png" => Ok(PhotoFormat::Png),
+            _ => Err(format!("{} is not a photo format, use jpeg or png", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhotoOptions {
+    pub format: PhotoFormat,
+    /// Crop the middle to the 35:45 passport proportions
+    pub passport: bool,
+    /// Scale to this width in pixels, keeping the proportions
+    pub width: Option<u32>,
+}
+
+fn crop_to_passport(image: DynamicImage) -> Result<DynamicImage, String> {
+    let (width, height) = image.dimensions();
+    let cropped = if width as u64 * PASSPORT_HEIGHT as u64 > height as u64 * PASSPORT_WIDTH as u64 {
+        let cropped = (height as u64 * PASSPORT_WIDTH as u64 / PASSPORT_HEIGHT as u64) as u32;
+        image.crop_imm((width - cropped) / 2, 0, cropped, height)
+    } else {
+        let cropped = (width as u64 * PASSPORT_HEIGHT as u64 / PASSPORT_WIDTH as u64) as u32;
+        image.crop_imm(0, (height - cropped) / 2, width, cropped)
+    };
+    if cropped.width() == 0 || cropped.height() == 0 {
+        return Err(format!("Photo of {}x{} pixels is too small to crop", width, height));
+    }
+    Ok(cropped)
+}
+
+/// Decodes the card photo and encodes it again, which leaves any metadata of the original behind.
+pub fn convert(data: &[u8], options: &PhotoOptions) -> Result<Vec<u8>, String> {
+    let mut image = image::load_from_memory(data).map_err(|err| format!("Photo can't be decoded: {}", err))?;
+    if options.passport {
+        image = crop_to_passport(image)?;
+    }
+    if let Some(width) = options.width {
+        if width == 0 {
+            return Err("Photo width must be at least one pixel".to_string());
+        }
+        let height = (image.height() as u64 * width as u64 / image.width().max(1) as u64).max(1) as u32;
+        image = image.resize_exact(width, height, image::imageops::FilterType::Lanczos3);
+    }
+
+    let mut out = Cursor::new(vec![]);
+    match options.format {
+        PhotoFormat::Jpeg => {
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).encode_image(&rgb).map_err(|err| err.to_string())?;
+        }
+        PhotoFormat::Png => image.write_to(&mut out, ImageOutputFormat::Png).map_err(|err| err.to_string())?,
+    }
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 128])));
+        let mut out = Cursor::new(vec![]);
+        image.write_to(&mut out, ImageOutputFormat::Jpeg(JPEG_QUALITY)).unwrap();
+        out.into_inner()
+    }
+
+    /// The same JPEG with an Exif APP1 segment right after SOI
+    fn with_exif(jpeg: &[u8]) -> Vec<u8> {
+        let payload = b"Exif\0\0MM\0*\0\0\0\x08\0\0";
+        let len = (payload.len() + 2) as u16;
+        [&jpeg[..2], &[0xff, 0xe1], &len.to_be_bytes(), payload, &jpeg[2..]].concat()
+    }
+
+    /// Markers of the segments before the image data
+    fn markers(jpeg: &[u8]) -> Vec<u8> {
+        let mut markers = vec![];
+        let mut index = 2;
+        while index + 4 <= jpeg.len() && jpeg[index] == 0xff && jpeg[index + 1] != 0xda {
+            markers.push(jpeg[index + 1]);
+            index += 2 + u16::from_be_bytes([jpeg[index + 2], jpeg[index + 3]]) as usize;
+        }
+        markers
+    }
+
+    fn dimensions(data: &[u8]) -> (u32, u32) {
+        image::load_from_memory(data).unwrap().dimensions()
+    }
+
+    fn options(passport: bool, width: Option<u32>) -> PhotoOptions {
+        PhotoOptions { format: PhotoFormat::Jpeg, passport, width }
+    }
+
+    #[test]
+    fn crops_to_passport_proportions() {
+        assert_eq!(dimensions(&convert(&jpeg(100, 100), &options(true, None)).unwrap()), (77, 100));
+        assert_eq!(dimensions(&convert(&jpeg(70, 200), &options(true, None)).unwrap()), (70, 90));
+        assert_eq!(dimensions(&convert(&jpeg(35, 45), &options(true, None)).unwrap()), (35, 45));
+    }
+
+    #[test]
+    fn resizes_keeping_the_proportions() {
+        assert_eq!(dimensions(&convert(&jpeg(70, 90), &options(false, Some(35))).unwrap()), (35, 45));
+        assert_eq!(dimensions(&convert(&jpeg(100, 100), &options(true, Some(350))).unwrap()), (350, 454));
+        assert_eq!(dimensions(&convert(&jpeg(100, 1), &options(false, Some(10))).unwrap()), (10, 1));
+    }
+
+    #[test]
+    fn rejects_empty_results() {
+        assert!(convert(&jpeg(1, 1), &options(true, None)).is_err());
+        assert!(convert(&jpeg(100, 1), &options(true, None)).is_err());
+        assert!(convert(&jpeg(10, 10), &options(false, Some(0))).is_err());
+        assert!(convert(b"not a photo", &options(false, None)).is_err());
+    }
+
+    #[test]
+    fn drops_exif() {
+        let original = with_exif(&jpeg(40, 50));
+        assert!(markers(&original).contains(&0xe1));
+        let converted = convert(&original, &options(false, None)).unwrap();
+        assert_eq!(image::guess_format(&converted).unwrap(), ImageFormat::Jpeg);
+        assert!(!markers(&converted).contains(&0xe1));
+
+        let png = convert(&original, &PhotoOptions { format: PhotoFormat::Png, passport: false, width: None }).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(PhotoFormat::from_path("photo.JPG"), Some(PhotoFormat::Jpeg));
+        assert_eq!(PhotoFormat::from_path("out/photo.png"), Some(PhotoFormat::Png));
+        assert_eq!(PhotoFormat::from_path("photo.gif"), None);
+        assert_eq!(PhotoFormat::from_path("photo"), None);
+    }
+}
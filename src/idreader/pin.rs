@@ -0,0 +1,137 @@
+use std::fmt;
+use pcsc::*;
+use zeroize::Zeroizing;
+
+use super::apdu::transmit;
+use super::reader::CardReader;
+
+pub const PIN_MIN_LENGTH: usize = 4;
+pub const PIN_MAX_LENGTH: usize = 8;
+/// Reference of the card holder PIN guarding the signing and authentication keys
+pub const PIN_REFERENCE: u8 = 0x80;
+pub const PUK_LENGTH: usize = 8;
+/// Reference of the PUK used to reset the PIN retry counter
+pub const PUK_REFERENCE: u8 = 0x81;
+const PIN_PADDING: u8 = 0xFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinStatus {
+    Verified,
+    Remaining(u8),
+    Blocked,
+}
+
+impl PinStatus {
+    fn from_sw(sw: [u8; 2]) -> Result<PinStatus, String> {
+        match sw {
+            [0x90, 0x00] => Ok(PinStatus::Verified),
+            [0x63, 0xc0] => Ok(PinStatus::Blocked),
+            [0x63, counter] if counter & 0xf0 == 0xc0 => Ok(PinStatus::Remaining(counter & 0x0f)),
+            [0x69, 0x83] | [0x69, 0x84] => Ok(PinStatus::Blocked),
+            _ => Err(format!("Reader returned error code at verify function {:x?}", sw)),
+        }
+    }
+}
+
+impl fmt::Display for PinStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PinStatus::Verified => write!(f, "PIN verified"),
+            PinStatus::Remaining(1) => write!(f, "PIN not verified, 1 attempt left before the PIN is blocked"),
+            PinStatus::Remaining(n) => write!(f, "PIN not verified, {} attempts left", n),
+            PinStatus::Blocked => write!(f, "PIN is blocked, it has to be unblocked with the PUK"),
+        }
+    }
+}
+
+pub fn validate_pin(pin: &str) -> Result<(), String> {
+    if pin.len() < PIN_MIN_LENGTH || pin.len() > PIN_MAX_LENGTH {
+        return Err(format!("PIN must have between {} and {} digits", PIN_MIN_LENGTH, PIN_MAX_LENGTH));
+    }
+    if !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("PIN must contain only digits".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_puk(puk: &str) -> Result<(), String> {
+    if puk.len() != PUK_LENGTH || !puk.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("PUK must have exactly {} digits", PUK_LENGTH));
+    }
+    Ok(())
+}
+
+fn pin_block(pin: &str) -> Zeroizing<Vec<u8>> {
+    let mut block = Zeroizing::new(vec![PIN_PADDING; PIN_MAX_LENGTH]);
+    block[..pin.len()].copy_from_slice(pin.as_bytes());
+    block
+}
+
+/// Queries a retry counter with an empty VERIFY, which does not consume an attempt.
+fn retry_status(reader: &dyn CardReader, card: &Card, reference: u8) -> Result<PinStatus, String> {
+    reader.select_pki(card)?;
+    let response = transmit(card, &[0x00, 0x20, 0x00, reference], false)?;
+    PinStatus::from_sw(response.sw)
+}
+
+pub fn pin_status(reader: &dyn CardReader, card: &Card) -> Result<PinStatus, String> {
+    retry_status(reader, card, PIN_REFERENCE)
+}
+
+pub fn puk_status(reader: &dyn CardReader, card: &Card) -> Result<PinStatus, String> {
+    retry_status(reader, card, PUK_REFERENCE)
+}
+
+/// A wrong PIN is not an error, the returned status tells how many attempts are left.
+pub fn verify_pin(reader: &dyn CardReader, card: &Card, pin: &str) -> Result<PinStatus, String> {
+    validate_pin(pin)?;
+
+    // Don't send anything to a card that already blocked the PIN
+    let status = pin_status(reader, card)?;
+    if status == PinStatus::Blocked {
+        return Ok(status);
+    }
+
+    let block = pin_block(pin);
+    let mut apdu = Zeroizing::new(vec![0x00, 0x20, 0x00, PIN_REFERENCE, block.len() as u8]);
+    apdu.extend_from_slice(&block);
+    let response = transmit(card, &apdu, false)?;
+    PinStatus::from_sw(response.sw)
+}
+
+/// CHANGE REFERENCE DATA. The returned status refers to the current PIN.
+pub fn change_pin(reader: &dyn CardReader, card: &Card, old_pin: &str, new_pin: &str) -> Result<PinStatus, String> {
+    validate_pin(old_pin)?;
+    validate_pin(new_pin)?;
+
+    let status = pin_status(reader, card)?;
+    if status == PinStatus::Blocked {
+        return Ok(status);
+    }
+
+    let old_block = pin_block(old_pin);
+    let new_block = pin_block(new_pin);
+    let mut apdu = Zeroizing::new(vec![0x00, 0x24, 0x00, PIN_REFERENCE, (old_block.len() + new_block.len()) as u8]);
+    apdu.extend_from_slice(&old_block);
+    apdu.extend_from_slice(&new_block);
+    let response = transmit(card, &apdu, false)?;
+    PinStatus::from_sw(response.sw)
+}
+
+/// RESET RETRY COUNTER. The returned status refers to the PUK, `Verified` means the PIN was reset.
+pub fn unblock_pin(reader: &dyn CardReader, card: &Card, puk: &str, new_pin: &str) -> Result<PinStatus, String> {
+    validate_puk(puk)?;
+    validate_pin(new_pin)?;
+
+    let status = puk_status(reader, card)?;
+    if status == PinStatus::Blocked {
+        return Ok(status);
+    }
+
+    let new_block = pin_block(new_pin);
+    let mut apdu = Zeroizing::new(vec![0x00, 0x2c, 0x00, PIN_REFERENCE, (puk.len() + new_block.len()) as u8]);
+    apdu.extend_from_slice(puk.as_bytes());
+    apdu.extend_from_slice(&new_block);
+    let response = transmit(card, &apdu, false)?;
+    PinStatus::from_sw(response.sw)
+}
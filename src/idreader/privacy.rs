@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use sha2::{Digest, Sha256};
+
+use super::document::{IdentityDocument, DATE_TAGS};
+use super::reader::{PersonalIdTag, ID_TAGS};
+
+/// Fields `address` stands for: everything but the municipality and the country
+const ADDRESS_TAGS: &[PersonalIdTag] = &[
+    PersonalIdTag::Place,
+    PersonalIdTag::Street,
+    PersonalIdTag::HouseNumber,
+    PersonalIdTag::HouseLetter,
+    PersonalIdTag::Entrance,
+    PersonalIdTag::Floor,
+    PersonalIdTag::AppartmentNumber,
+    PersonalIdTag::AddressDate,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mask {
+    /// The first four and the last three characters shown, e.g. 0101******123, or only the first
+    /// character of values up to ten characters long
+    Partial,
+    Hidden,
+}
+
+impl Mask {
+    fn apply(&self, value: &str) -> Option<String> {
+        match self {
+            Mask::Hidden => None,
+            Mask::Partial => {
+                let chars: Vec<char> = value.chars().collect();
+                let (head, tail) = if chars.len() > 10 { (4, 3) } else { (chars.len().min(1), 0) };
+                let shown = |index| index < head || index >= chars.len() - tail;
+                Some(chars.iter().enumerate().map(|(index, char)| if shown(index) { *char } else { '*' }).collect())
+            }
+        }
+    }
+}
+
+/// Which card values the outputs leave out or show only in part
+#[derive(Clone, Debug, Default)]
+pub struct Privacy {
+    fields: HashMap<PersonalIdTag, Mask>,
+    /// Leave the photo out
+    pub photo: bool,
+}
+
+impl Privacy {
+    /// JMBG shown in part, address reduced to the municipality, no photo
+    pub fn standard() -> Privacy {
+        "jmbg=partial,address,photo".parse().unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && !self.photo
+    }
+
+    pub fn set(&mut self, tag: PersonalIdTag, mask: Mask) {
+        self.fields.insert(tag, mask);
+    }
+
+    pub fn mask(&self, tag: PersonalIdTag) -> Option<Mask> {
+        self.fields.get(&tag).copied()
+    }
+
+    /// `value` of `tag` as the outputs may show it, `None` when it is hidden
+    pub fn apply(&self, tag: PersonalIdTag, value: &str) -> Option<String> {
+        match self.mask(tag) {
+            Some(mask) => mask.apply(value),
+            None => Some(value.to_string()),
+        }
+    }
+
+    /// Name for files written from a card with these numbers: the personal number, or the document
+    /// number when the personal number is masked. When both are, the start of a SHA-256 of the personal
+    /// number, so files of different cards don't overwrite each other without showing either number.
+    pub fn file_stem(&self, personal_number: &str, document_number: &str) -> String {
+        let shown = |tag, number: &str| self.mask(tag).is_none() && !number.is_empty();
+        if shown(PersonalIdTag::PersonalNumber, personal_number) {
+            personal_number.to_string()
+        } else if shown(PersonalIdTag::DocRegNo, document_number) {
+            document_number.to_string()
+        } else if !personal_number.is_empty() {
+            Sha256::digest(personal_number.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+        } else {
+            "id".to_string()
+        }
+    }
+
+    /// `document` with the masked values emptied or shown in part. Errors about masked fields are
+    /// dropped, as their reasons may tell what the value was.
+    pub fn document(&self, mut document: IdentityDocument) -> IdentityDocument {
+        let text = |tag, value: &mut String| *value = self.apply(tag, value).unwrap_or_default();
+        let optional = |tag, value: &mut Option<String>| *value = value.as_deref().and_then(|value| self.apply(tag, value));
+        let hidden = |tag| self.mask(tag).is_some();
+
+        let person = &mut document.person;
+        text(PersonalIdTag::Surname, &mut person.surname);
+        text(PersonalIdTag::GivenName, &mut person.given_name);
+        text(PersonalIdTag::ParentGivenName, &mut person.parent_given_name);
+        text(PersonalIdTag::PersonalNumber, &mut person.personal_number);
+        text(PersonalIdTag::PlaceOfBirth, &mut person.place_of_birth);
+        text(PersonalIdTag::CommunityOfBirth, &mut person.community_of_birth);
+        text(PersonalIdTag::StateOfBirth, &mut person.state_of_birth);
+        if hidden(PersonalIdTag::Sex) {
+            person.sex = None;
+        }
+        if hidden(PersonalIdTag::DateOfBirth) {
+            person.date_of_birth = None;
+        }
+
+        let doc = &mut document.document;
+        text(PersonalIdTag::DocRegNo, &mut doc.number);
+        text(PersonalIdTag::IssuingAuthority, &mut doc.issuing_authority);
+        if hidden(PersonalIdTag::IssuingDate) {
+            doc.issuing_date = None;
+        }
+        if hidden(PersonalIdTag::ExpiryDate) {
+            doc.expiry_date = None;
+        }
+
+        let address = &mut document.address;
+        text(PersonalIdTag::State, &mut address.state);
+        text(PersonalIdTag::Community, &mut address.community);
+        text(PersonalIdTag::Place, &mut address.place);
+        address.street_masked = address.street.is_some() && self.mask(PersonalIdTag::Street) == Some(Mask::Hidden);
+        optional(PersonalIdTag::Street, &mut address.street);
+        optional(PersonalIdTag::HouseNumber, &mut address.house_number);
+        optional(PersonalIdTag::HouseLetter, &mut address.house_letter);
+        optional(PersonalIdTag::Entrance, &mut address.entrance);
+        optional(PersonalIdTag::Floor, &mut address.floor);
+        optional(PersonalIdTag::AppartmentNumber, &mut address.apartment_number);
+        if hidden(PersonalIdTag::AddressDate) {
+            address.address_date = None;
+        }
+
+        if self.photo {
+            document.photo.data.clear();
+        }
+        document.errors.retain(|error| !hidden(error.tag));
+        document
+    }
+}
+
+/// A comma separated list of FIELD or FIELD=MODE, MODE being partial or hidden, the default.
+/// FIELD is a JSON name such as PersonalNumber, or jmbg, address (all but the municipality and
+/// country) or photo.
+impl FromStr for Privacy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Privacy, String> {
+        let mut privacy = Privacy::default();
+        for rule in value.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+            let (field, mask) = match rule.split_once('=') {
+                Some((field, "partial")) => (field, Mask::Partial),
+                Some((field, "hidden")) => (field, Mask::Hidden),
+                Some((_, mode)) => return Err(format!("{} is not a mask, use partial or hidden", mode)),
+                None => (rule, Mask::Hidden),
+            };
+            let tags: Vec<PersonalIdTag> = match field.to_lowercase().as_str() {
+                "photo" if mask == Mask::Hidden => {
+                    privacy.photo = true;
+                    continue;
+                }
+                "photo" => return Err("The photo can only be hidden".to_string()),
+                "jmbg" => vec![PersonalIdTag::PersonalNumber],
+                "address" => ADDRESS_TAGS.to_vec(),
+                name => match ID_TAGS.iter().find(|tag| tag.json_id.to_lowercase() == name) {
+                    Some(tag) => vec![tag.id],
+                    None => return Err(format!("{} is not a field", field)),
+                },
+            };
+            if mask == Mask::Partial && tags.iter().any(|tag| DATE_TAGS.contains(tag)) {
+                return Err(format!("{} can only be hidden", field));
+            }
+            for tag in tags {
+                privacy.set(tag, mask);
+            }
+        }
+        Ok(privacy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_mask() {
+        assert_eq!(Mask::Partial.apply("0101990710008").as_deref(), Some("0101******008"));
+        assert_eq!(Mask::Partial.apply("Петровић").as_deref(), Some("П*******"));
+        assert_eq!(Mask::Partial.apply("").as_deref(), Some(""));
+        assert_eq!(Mask::Hidden.apply("0101990710008"), None);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let privacy: Privacy = "jmbg=partial, Street ,GivenName=hidden".parse().unwrap();
+        assert_eq!(privacy.mask(PersonalIdTag::PersonalNumber), Some(Mask::Partial));
+        assert_eq!(privacy.mask(PersonalIdTag::Street), Some(Mask::Hidden));
+        assert_eq!(privacy.mask(PersonalIdTag::GivenName), Some(Mask::Hidden));
+        assert_eq!(privacy.mask(PersonalIdTag::Surname), None);
+        assert!(!privacy.photo);
+        assert!("".parse::<Privacy>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn standard_privacy() {
+        let privacy = Privacy::standard();
+        assert_eq!(privacy.apply(PersonalIdTag::PersonalNumber, "0101990710008").as_deref(), Some("0101******008"));
+        assert_eq!(privacy.apply(PersonalIdTag::Street, "Bulevar kralja Aleksandra"), None);
+        assert_eq!(privacy.apply(PersonalIdTag::AddressDate, "01012020"), None);
+        assert_eq!(privacy.apply(PersonalIdTag::Community, "Zvezdara").as_deref(), Some("Zvezdara"));
+        assert!(privacy.photo);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!("nickname".parse::<Privacy>().is_err());
+        assert!("jmbg=blurred".parse::<Privacy>().is_err());
+        assert!("photo=partial".parse::<Privacy>().is_err());
+        assert!("DateOfBirth=partial".parse::<Privacy>().is_err());
+        assert!("address=partial".parse::<Privacy>().is_err());
+    }
+
+    #[test]
+    fn file_stem_never_shows_a_masked_number() {
+        let none = Privacy::default();
+        assert_eq!(none.file_stem("0101990710008", "012345678"), "0101990710008");
+        let jmbg: Privacy = "jmbg=partial".parse().unwrap();
+        assert_eq!(jmbg.file_stem("0101990710008", "012345678"), "012345678");
+        let both: Privacy = "jmbg,DocRegNo=partial".parse().unwrap();
+        let stem = both.file_stem("0101990710008", "012345678");
+        assert_eq!(stem.len(), 16);
+        assert!(stem.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(stem, both.file_stem("1503985805127", "012345678"));
+        assert_eq!(both.file_stem("", ""), "id");
+    }
+}
@@ -1,17 +1,23 @@
 use byteorder::{LittleEndian, ReadBytesExt};
 use std::{collections::HashMap, fmt};
+use chrono::{DateTime, NaiveDate, Utc};
 use pcsc::*;
-use super::{gemalto_card_reader::*, apollo_card_reader::*};
+use serde::{Deserialize, Serialize};
+use super::{gemalto_card_reader::*, apollo_card_reader::*, offline_card_reader::*};
+use super::document::{parse_date, IdentityDocument, DATE_TAGS};
+use super::json::{JsonId, JsonOptions, SCHEMA_VERSION};
+use super::labels::{self, Label, Language};
+use super::output::OutputOptions;
 
 pub const BLOCK_SIZE: u32 = 254;
-pub const PERSONAL_FILE: &'static [u8] = &[0x0F, 0x03];
-pub const DOCUMENT_FILE: &'static [u8] = &[0x0F, 0x02];
-pub const RESIDENCE_FILE: &'static [u8] = &[0x0F, 0x04];
-pub const PHOTO_FILE: &'static [u8] = &[0x0F, 0x06];
-pub const SUCCESS_RESPONSE: &'static [u8]= &[0x90, 0x00];
+pub const PERSONAL_FILE: &[u8] = &[0x0F, 0x03];
+pub const DOCUMENT_FILE: &[u8] = &[0x0F, 0x02];
+pub const RESIDENCE_FILE: &[u8] = &[0x0F, 0x04];
+pub const PHOTO_FILE: &[u8] = &[0x0F, 0x06];
+pub const SUCCESS_RESPONSE: &[u8]= &[0x90, 0x00];
 
 #[derive(Eq, Hash, PartialEq)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum PersonalIdTag {
     DocRegNo = 1546,
     IssuingDate = 1549,
@@ -41,43 +47,43 @@ pub enum PersonalIdTag {
 
 pub struct PersonalIdTagType {
     pub id: PersonalIdTag,
-    pub description: &'static str,
+    /// Member name in the JSON output, labels are in `labels::text`
     pub json_id: &'static str
 }
 
 impl Default for PersonalIdTagType {
     fn default() -> Self {
-        Self { id: PersonalIdTag::None, description: "", json_id: ""}
-    }
-}
-
-pub const ID_TAG_NONE: &'static PersonalIdTagType = &PersonalIdTagType{id: PersonalIdTag::None, description: "", json_id: ""};
-
-pub const ID_TAGS: &'static [PersonalIdTagType] = 
-        &[PersonalIdTagType{id: PersonalIdTag::DocRegNo, description: "Broj dokumenta", json_id: "DocRegNo"},
-          PersonalIdTagType{id: PersonalIdTag::IssuingDate, description: "Datum izdavanja", json_id: "IssuingDate"},
-          PersonalIdTagType{id: PersonalIdTag::ExpiryDate, description: "Datum isteka", json_id: "ExpiryDate"},
-          PersonalIdTagType{id: PersonalIdTag::IssuingAuthority, description: "Izdato od", json_id: "IssuingAuthority"},
-          PersonalIdTagType{id: PersonalIdTag::PersonalNumber, description: "JMBG", json_id: "PersonalNumber"},
-          PersonalIdTagType{id: PersonalIdTag::Surname, description: "Prezime", json_id: "Surname"},
-          PersonalIdTagType{id: PersonalIdTag::GivenName, description: "Ime", json_id: "GivenName"},
-          PersonalIdTagType{id: PersonalIdTag::ParentGivenName, description: "Ime roditelja", json_id: "ParentGivenName"},
-          PersonalIdTagType{id: PersonalIdTag::Sex, description: "Pol", json_id: "Sex"},
-          PersonalIdTagType{id: PersonalIdTag::PlaceOfBirth, description: "Mesto rođenja", json_id: "PlaceOfBirth"},
-          PersonalIdTagType{id: PersonalIdTag::CommunityOfBirth, description: "Opština rođenja", json_id: "CommunityOfBirth"},
-          PersonalIdTagType{id: PersonalIdTag::StateOfBirth, description: "Država rođenja", json_id: "StateOfBirth"},
-          PersonalIdTagType{id: PersonalIdTag::DateOfBirth, description: "Datum rođenja", json_id: "DateOfBirth"},
-          PersonalIdTagType{id: PersonalIdTag::State, description: "Država", json_id: "State"},
-          PersonalIdTagType{id: PersonalIdTag::Community, description: "Opština", json_id: "Community"},
-          PersonalIdTagType{id: PersonalIdTag::Place, description: "Mesto", json_id: "Place"},
-          PersonalIdTagType{id: PersonalIdTag::Street, description: "Ulica", json_id: "Street"},
-          PersonalIdTagType{id: PersonalIdTag::HouseNumber, description: "Kućni broj", json_id: "HouseNumber"},
-          PersonalIdTagType{id: PersonalIdTag::HouseLetter, description: "Kućna oznaka", json_id: "HouseLetter"},
-          PersonalIdTagType{id: PersonalIdTag::Entrance, description: "Broj ulaza", json_id: "Entrance"},
-          PersonalIdTagType{id: PersonalIdTag::Floor, description: "Sprat broj", json_id: "Floor"},
-          PersonalIdTagType{id: PersonalIdTag::AppartmentNumber, description: "Broj stana", json_id: "AppartmentNumber"},
-          PersonalIdTagType{id: PersonalIdTag::AddressDate, description: "Datum adrese", json_id: "AddressDate"},
-          PersonalIdTagType{id: PersonalIdTag::None, description: "", json_id: ""},
+        Self { id: PersonalIdTag::None, json_id: ""}
+    }
+}
+
+pub const ID_TAG_NONE: &PersonalIdTagType = &PersonalIdTagType{id: PersonalIdTag::None, json_id: ""};
+
+pub const ID_TAGS: &[PersonalIdTagType] = 
+        &[PersonalIdTagType{id: PersonalIdTag::DocRegNo, json_id: "DocRegNo"},
+          PersonalIdTagType{id: PersonalIdTag::IssuingDate, json_id: "IssuingDate"},
+          PersonalIdTagType{id: PersonalIdTag::ExpiryDate, json_id: "ExpiryDate"},
+          PersonalIdTagType{id: PersonalIdTag::IssuingAuthority, json_id: "IssuingAuthority"},
+          PersonalIdTagType{id: PersonalIdTag::PersonalNumber, json_id: "PersonalNumber"},
+          PersonalIdTagType{id: PersonalIdTag::Surname, json_id: "Surname"},
+          PersonalIdTagType{id: PersonalIdTag::GivenName, json_id: "GivenName"},
+          PersonalIdTagType{id: PersonalIdTag::ParentGivenName, json_id: "ParentGivenName"},
+          PersonalIdTagType{id: PersonalIdTag::Sex, json_id: "Sex"},
+          PersonalIdTagType{id: PersonalIdTag::PlaceOfBirth, json_id: "PlaceOfBirth"},
+          PersonalIdTagType{id: PersonalIdTag::CommunityOfBirth, json_id: "CommunityOfBirth"},
+          PersonalIdTagType{id: PersonalIdTag::StateOfBirth, json_id: "StateOfBirth"},
+          PersonalIdTagType{id: PersonalIdTag::DateOfBirth, json_id: "DateOfBirth"},
+          PersonalIdTagType{id: PersonalIdTag::State, json_id: "State"},
+          PersonalIdTagType{id: PersonalIdTag::Community, json_id: "Community"},
+          PersonalIdTagType{id: PersonalIdTag::Place, json_id: "Place"},
+          PersonalIdTagType{id: PersonalIdTag::Street, json_id: "Street"},
+          PersonalIdTagType{id: PersonalIdTag::HouseNumber, json_id: "HouseNumber"},
+          PersonalIdTagType{id: PersonalIdTag::HouseLetter, json_id: "HouseLetter"},
+          PersonalIdTagType{id: PersonalIdTag::Entrance, json_id: "Entrance"},
+          PersonalIdTagType{id: PersonalIdTag::Floor, json_id: "Floor"},
+          PersonalIdTagType{id: PersonalIdTag::AppartmentNumber, json_id: "AppartmentNumber"},
+          PersonalIdTagType{id: PersonalIdTag::AddressDate, json_id: "AddressDate"},
+          PersonalIdTagType{id: PersonalIdTag::None, json_id: ""},
          ];
          
 pub struct PersonalIdItem {
@@ -95,31 +101,125 @@ impl Default for PersonalIdItem {
 impl PersonalIdItem {
     pub fn new(tag: &'static PersonalIdTagType, map: &HashMap<u16, Vec<u8>>) -> Option<PersonalIdItem> {
         let val = map.get(&(tag.id as u16))?;
-        let s = match std::str::from_utf8(&val) {
+        Self::from_bytes(tag, val)
+    }
+
+    /// `None` for values that are not valid UTF-8, they stay available as raw TLVs
+    pub fn from_bytes(tag: &'static PersonalIdTagType, val: &[u8]) -> Option<PersonalIdItem> {
+        let s = match std::str::from_utf8(val) {
             Ok(v) => v,
-            Err(_) => {return Option::None}//panic!("Invalid UTF-8 sequence: {}", e),
+            Err(_) => {return Option::None}
         };
-        Some(PersonalIdItem {tag: tag, value: s.to_string()})
+        Some(PersonalIdItem {tag, value: s.to_string()})
     }
 }
 
+/// A TLV as read from the card, whether or not it became a `PersonalIdItem`
+pub struct RawTlv {
+    /// File the TLV was read from, e.g. `PERSONAL_FILE`
+    pub file: &'static [u8],
+    pub tag: u16,
+    pub value: Vec<u8>,
+    /// Tag is in `ID_TAGS` and the value is valid UTF-8
+    pub known: bool,
+}
+
 impl fmt::Display for PersonalIdItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{: >20}: {}", self.tag.description, self.value)
+        write!(f, "{: >20}: {}", labels::text(Label::Field(self.tag.id), Language::SerbianLatin), self.value)
+    }
+}
+
+/// Card generations, told apart by their ATR
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CardProfile {
+    Apollo,
+    Gemalto,
+    GemaltoGcn3,
+    GemaltoSce8C1,
+    GemaltoSce8C2,
+}
+
+impl CardProfile {
+    pub fn from_atr(atr: &[u8]) -> Option<CardProfile> {
+        match atr {
+            APOLLO_CARD_TYPE => Some(CardProfile::Apollo),
+            GEMALTO_CARD_TYPE => Some(CardProfile::Gemalto),
+            GEMALTO_NEW_CARD_TYPE => Some(CardProfile::GemaltoGcn3),
+            GEMALTO_EVEN_NEWER_CARD_TYPE => Some(CardProfile::GemaltoSce8C1),
+            GEMALTO_CARD_TYPE_1 => Some(CardProfile::GemaltoSce8C2),
+            _ => None,
+        }
+    }
+
+    pub fn card_reader(&self) -> Box<dyn CardReader> {
+        match self {
+            CardProfile::Apollo => Box::new(ApolloCardReader{}),
+            _ => Box::new(GemaltoCardReader{}),
+        }
+    }
+}
+
+impl std::str::FromStr for CardProfile {
+    type Err = String;
+
+    /// From the name `Display` writes
+    fn from_str(value: &str) -> Result<CardProfile, String> {
+        [CardProfile::Apollo, CardProfile::Gemalto, CardProfile::GemaltoGcn3, CardProfile::GemaltoSce8C1, CardProfile::GemaltoSce8C2]
+            .into_iter()
+            .find(|profile| profile.to_string() == value)
+            .ok_or_else(|| format!("{} is not a card profile", value))
+    }
+}
+
+impl fmt::Display for CardProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardProfile::Apollo => write!(f, "Apollo"),
+            CardProfile::Gemalto => write!(f, "Gemalto"),
+            CardProfile::GemaltoGcn3 => write!(f, "Gemalto SCE7 GCN3"),
+            CardProfile::GemaltoSce8C1 => write!(f, "Gemalto SCE 8.0 C1"),
+            CardProfile::GemaltoSce8C2 => write!(f, "Gemalto SCE 8.0 C2"),
+        }
     }
 }
 
+/// How reading one of the ID files went, `error` is `None` once it is read
+pub struct SectionRead {
+    /// Name from `chip::ID_FILES`, e.g. Personal
+    pub name: String,
+    pub error: Option<String>,
+}
+
 pub struct PersonalId {
     pub personal: HashMap<PersonalIdTag, PersonalIdItem>,
     pub image: Vec<u8>,
     pub card_reader: Box<dyn CardReader>,
+    pub atr: Vec<u8>,
+    pub profile: CardProfile,
+    /// Every TLV of the ID files in card order
+    pub tlvs: Vec<RawTlv>,
+    /// Name of the PC/SC reader the card is in
+    pub reader: Option<String>,
+    /// When `read_id` read the card
+    pub read_at: Option<DateTime<Utc>>,
+    /// The ID files `read_id` tried, in the order it read them
+    pub sections: Vec<SectionRead>,
 }
 
-pub trait CardReader {
+/// `Send` so a reader can be kept alongside its card, as the PKCS#11 module does
+pub trait CardReader: Send {
     fn select_aid(&self, card: &Card) -> Result<Vec<u8>, String>;
+    fn select_pki(&self, card: &Card) -> Result<Vec<u8>, String>;
+    /// Returns the FCP/FCI the card answers with, at most `expected_result_size` bytes, 0 for all of it
     fn select_file(&self, card: &Card, file: &[u8], expected_result_size: u8) -> Result<Vec<u8>, String>;
     fn read_binary(&self, card: &Card, offset: u32, length: u32) -> Result<Vec<u8>, String>;
     fn read_raw_file(&self, card: &Card, file: &[u8], strip_tag: bool) -> Result<Vec<u8>, String>;
+    /// Size of the header in front of each ID file and the offset of the little endian length of the
+    /// rest within it
+    fn file_header(&self) -> (u32, usize) {
+        (4, 2)
+    }
 }
 
 impl PersonalId {
@@ -128,21 +228,129 @@ impl PersonalId {
             Err(error) => {return Err(error.to_string());},
             Ok(result) => result
         };
-        if buffer == APOLLO_CARD_TYPE { return Ok(PersonalId { personal: HashMap::new(), image: vec![], card_reader: Box::new(ApolloCardReader{})});}
-        if buffer == GEMALTO_CARD_TYPE { return Ok(PersonalId { personal: HashMap::new(), image: vec![], card_reader: Box::new(GemaltoCardReader{})});}
-        if buffer == GEMALTO_NEW_CARD_TYPE { return Ok(PersonalId { personal: HashMap::new(), image: vec![], card_reader: Box::new(GemaltoCardReader{})});}
-        if buffer == GEMALTO_EVEN_NEWER_CARD_TYPE { return Ok(PersonalId { personal: HashMap::new(), image: vec![], card_reader: Box::new(GemaltoCardReader{})});}
-        if buffer == GEMALTO_CARD_TYPE_1 { return Ok(PersonalId { personal: HashMap::new(), image: vec![], card_reader: Box::new(GemaltoCardReader{})});}
-        return Err("Unknown card type".to_string());
-    }
-    fn fit_in(&mut self, map: &HashMap<u16, Vec<u8>>) {
-        for tag in ID_TAGS.iter().enumerate() {
-            PersonalIdItem::new(tag.1, map).and_then(|item: PersonalIdItem| Some(self.personal.insert(item.tag.id, item)));    
+        let profile = match CardProfile::from_atr(&buffer) {
+            Some(profile) => profile,
+            None => return Err("Unknown card type".to_string()),
+        };
+        let reader = card.status2_owned().ok()
+            .and_then(|status| status.reader_names().first().map(|name| name.to_string_lossy().into_owned()));
+        Ok(PersonalId {
+            personal: HashMap::new(),
+            image: vec![],
+            card_reader: profile.card_reader(),
+            atr: buffer,
+            profile,
+            tlvs: vec![],
+            reader,
+            read_at: None,
+            sections: vec![],
+        })
+    }
+    /// Loads what `to_json` wrote, to render the outputs again without the card. Values stay as they were
+    /// written: masked, transliterated or in another date format they don't turn back into the card's.
+    pub fn from_json(text: &str) -> Result<PersonalId, String> {
+        let json: JsonId = serde_json::from_str(text).map_err(|err| format!("Not an ID JSON: {}", err))?;
+        let major = |version: &str| version.split('.').next().unwrap_or_default().to_string();
+        if major(&json.meta.schema_version) != major(SCHEMA_VERSION) {
+            return Err(format!("JSON schema version {} is not supported, this program reads {}", json.meta.schema_version, SCHEMA_VERSION));
+        }
+
+        // Hex digits are ASCII, so slicing by byte index below is safe
+        if !json.meta.atr.len().is_multiple_of(2) || !json.meta.atr.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex in Atr: {}", json.meta.atr));
+        }
+        let atr = (0..json.meta.atr.len() / 2)
+            .map(|i| u8::from_str_radix(&json.meta.atr[2 * i..2 * i + 2], 16).map_err(|_| format!("Invalid hex in Atr: {}", json.meta.atr)))
+            .collect::<Result<Vec<u8>, String>>()?;
+        let profile = match CardProfile::from_atr(&atr) {
+            Some(profile) => profile,
+            None => json.meta.card_profile.parse()?,
+        };
+        let read_at = match &json.meta.read_at {
+            Some(time) => Some(DateTime::parse_from_rfc3339(time).map_err(|err| format!("ReadAt: {}", err))?.with_timezone(&Utc)),
+            None => None,
+        };
+        let image = match &json.image {
+            Some(image) => base64::decode(image).map_err(|err| format!("Image: {}", err))?,
+            None => vec![],
+        };
+
+        let mut personal = HashMap::new();
+        for (json_id, value) in &json.fields {
+            let tag = match ID_TAGS.iter().find(|tag| tag.id != PersonalIdTag::None && tag.json_id == json_id) {
+                Some(tag) => tag,
+                None => continue,
+            };
+            let mut value = match value {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            // JSON dates are ISO 8601 by default, the card writes DDMMYYYY
+            if DATE_TAGS.contains(&tag.id) && parse_date(&value).is_err() {
+                if let Ok(date) = NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+                    value = date.format("%d%m%Y").to_string();
+                }
+            }
+            personal.insert(tag.id, PersonalIdItem { tag, value });
+        }
+
+        let files = [PERSONAL_FILE, DOCUMENT_FILE, RESIDENCE_FILE, PHOTO_FILE];
+        let mut tlvs = vec![];
+        for tlv in &json.unknown {
+            let file = match files.into_iter().find(|file| file.iter().map(|b| format!("{:02X}", b)).collect::<String>() == tlv.file) {
+                Some(file) => file,
+                None => continue,
+            };
+            let value = match (&tlv.value, &tlv.base64) {
+                (Some(text), _) => text.as_bytes().to_vec(),
+                (None, Some(encoded)) => base64::decode(encoded).map_err(|err| format!("Base64 of tag {}: {}", tlv.tag, err))?,
+                (None, None) => vec![],
+            };
+            tlvs.push(RawTlv { file, tag: tlv.tag, value, known: false });
+        }
+
+        Ok(PersonalId {
+            personal,
+            image,
+            card_reader: Box::new(OfflineCardReader {}),
+            atr,
+            profile,
+            tlvs,
+            reader: json.meta.reader.clone(),
+            read_at,
+            sections: json.meta.sections.iter().map(|section| SectionRead { name: section.name.clone(), error: section.error.clone() }).collect(),
+        })
+    }
+
+    fn fit_in(&mut self, file: &'static [u8], tlvs: Vec<(u16, Vec<u8>)>) {
+        for (tag, value) in tlvs {
+            let id_tag = ID_TAGS.iter().find(|id_tag| id_tag.id != PersonalIdTag::None && id_tag.id as u16 == tag);
+            let item = id_tag.and_then(|id_tag| PersonalIdItem::from_bytes(id_tag, &value));
+            let known = item.is_some();
+            if let Some(item) = item {
+                self.personal.insert(item.tag.id, item);
+            }
+            self.tlvs.push(RawTlv { file, tag, value, known });
         }
     }
 
-    fn parse_tlv(buffer: &Vec<u8>) -> Result<HashMap<u16, Vec<u8>>, String> {
-        let mut tlvs = HashMap::new();
+    /// Value of a field as the card stores it
+    pub fn value(&self, tag: PersonalIdTag) -> Option<&str> {
+        self.personal.get(&tag).map(|item| item.value.as_str())
+    }
+
+    /// The fields grouped into person, document, address and photo, with dates and sex parsed
+    pub fn document(&self) -> IdentityDocument {
+        IdentityDocument::new(self)
+    }
+
+    /// TLVs that did not become a `PersonalIdItem`: tags this reader does not know or values that are not UTF-8
+    pub fn unknown(&self) -> impl Iterator<Item = &RawTlv> {
+        self.tlvs.iter().filter(|tlv| !tlv.known)
+    }
+
+    fn parse_tlv(buffer: &[u8]) -> Result<Vec<(u16, Vec<u8>)>, String> {
+        let mut tlvs = vec![];
         let mut offset = 0;
     
         loop {
@@ -160,7 +368,10 @@ impl PersonalId {
             } as usize;
             offset += 4;
             let end = offset + length;
-            tlvs.insert(tag, buffer[offset..end].to_vec());
+            if end > buffer.len() {
+                return Err(format!("TLV {} is longer than its file", tag));
+            }
+            tlvs.push((tag, buffer[offset..end].to_vec()));
             offset = end;
     
             if offset >= buffer.len() {
@@ -170,34 +381,135 @@ impl PersonalId {
         Ok(tlvs)
     }
 
+    /// Reads the ID files. A file that can't be read is recorded in `sections` and the others are still read,
+    /// `Err` only when the application can't be selected or no file could be read.
     pub fn read_id(&mut self, card: &Card) -> Result<(),String> {
         self.card_reader.select_aid(card)?;
+        self.read_at = Some(Utc::now());
+        self.sections.clear();
 
-        let buffer = self.card_reader.read_raw_file(&card, PERSONAL_FILE, false)?;
-        let res = Self::parse_tlv(&buffer)?;
-        self.fit_in(&res);
+        for (name, file) in [("Personal", PERSONAL_FILE), ("Residence", RESIDENCE_FILE), ("Document", DOCUMENT_FILE)] {
+            let read = self.card_reader.read_raw_file(card, file, false)
+                .and_then(|buffer| Self::parse_tlv(&buffer))
+                .map(|res| self.fit_in(file, res));
+            self.sections.push(SectionRead { name: name.to_string(), error: read.err() });
+        }
 
-        let buffer = self.card_reader.read_raw_file(&card, RESIDENCE_FILE, false)?;
-        let res = Self::parse_tlv(&buffer)?;
-        self.fit_in(&res);
+        let read = self.card_reader.read_raw_file(card, PHOTO_FILE, true).map(|image| self.image = image);
+        self.sections.push(SectionRead { name: "Photo".to_string(), error: read.err() });
 
-        let buffer = self.card_reader.read_raw_file(&card, DOCUMENT_FILE, false)?;
-        let res = Self::parse_tlv(&buffer) ?;
-        self.fit_in(&res);
-        
-        self.image = self.card_reader.read_raw_file(&card, PHOTO_FILE, true)?;
-        
-        Ok(())
+        match self.sections.iter().find_map(|section| section.error.as_ref()) {
+            Some(error) if self.sections.iter().all(|section| section.error.is_some()) => Err(error.clone()),
+            _ => Ok(()),
+        }
     }
 
     pub fn to_json(&self) -> String {
-        let mut json_output: String = String::new();
-        json_output.push_str("{");
-        for (_tag, item ) in self.personal.iter() {
-            json_output.push_str(&format!("\"{}\": \"{}\",\n", &item.tag.json_id, &item.value));
+        self.to_json_with(&OutputOptions::default(), &JsonOptions::default())
+    }
+
+    /// Dates in ISO 8601 unless `options` say otherwise. A date that doesn't parse is written as read and explained in "Errors".
+    pub fn to_json_with(&self, options: &OutputOptions, json: &JsonOptions) -> String {
+        JsonId::new(self, options, json).to_json(json.pretty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idreader::script::Script;
+
+    fn sample() -> String {
+        serde_json::json!({
+            "Meta": {
+                "SchemaVersion": SCHEMA_VERSION,
+                "ToolVersion": "0.1.0",
+                "ReadAt": "2024-05-01T10:00:00Z",
+                "Reader": "Test reader 00 00",
+                "Atr": APOLLO_CARD_TYPE.iter().map(|b| format!("{:02X}", b)).collect::<String>(),
+                "CardProfile": "Apollo",
+                "Sections": [{ "Name": "Personal", "Read": true }, { "Name": "Photo", "Read": false, "Error": "6A82" }],
+            },
+            "Surname": "Петровић",
+            "GivenName": "Петар",
+            "PersonalNumber": "0101990710008",
+            "DateOfBirth": "1990-01-01",
+            "ExpiryDate": null,
+            "Validity": { "Status": "unknown", "DaysToExpiry": null },
+            "Address": { "Line": "", "Lines": [] },
+            "Errors": [],
+            "Image": "AQID",
+            "Unknown": [{ "File": "0F03", "Tag": 1600, "Value": "x" }],
+        }).to_string()
+    }
+
+    #[test]
+    fn reads_saved_json() {
+        let personal_id = PersonalId::from_json(&sample()).unwrap();
+        assert_eq!(personal_id.value(PersonalIdTag::Surname), Some("Петровић"));
+        assert_eq!(personal_id.value(PersonalIdTag::DateOfBirth), Some("01011990"));
+        assert_eq!(personal_id.value(PersonalIdTag::ExpiryDate), None);
+        assert_eq!(personal_id.atr, APOLLO_CARD_TYPE);
+        assert_eq!(personal_id.profile, CardProfile::Apollo);
+        assert_eq!(personal_id.image, [1, 2, 3]);
+        assert_eq!(personal_id.reader.as_deref(), Some("Test reader 00 00"));
+        assert_eq!(personal_id.sections[1].error.as_deref(), Some("6A82"));
+        assert_eq!(personal_id.unknown().map(|tlv| tlv.tag).collect::<Vec<_>>(), [1600]);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = PersonalId::from_json(&sample()).unwrap().to_json();
+        assert_eq!(PersonalId::from_json(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn invalid_dates_survive_a_json_round_trip() {
+        let personal_id = PersonalId::from_json(&sample().replace("\"1990-01-01\"", "\"3102199\"")).unwrap();
+        let json = personal_id.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["DateOfBirth"], "3102199");
+        assert_eq!(value["Errors"][0], "DateOfBirth: \"3102199\" is not a date");
+
+        let document = PersonalId::from_json(&json).unwrap().document();
+        let error = document.errors.iter().find(|error| error.tag == PersonalIdTag::DateOfBirth).unwrap();
+        assert_eq!(error.value, "3102199");
+        assert_eq!(document.person.date_of_birth, None);
+    }
+
+    #[test]
+    fn json_address_is_serbian_whatever_the_language() {
+        let mut value: serde_json::Value = serde_json::from_str(&sample()).unwrap();
+        value["Place"] = "Врбица".into();
+        value["Street"] = "Главна".into();
+        value["Floor"] = "2".into();
+        let personal_id = PersonalId::from_json(&value.to_string()).unwrap();
+        let cases = [
+            (None, None, "", "Главна бб, спрат 2, Врбица"),
+            (Some(Language::English), None, "", "Главна бб, спрат 2, Врбица"),
+            (Some(Language::English), Some(Script::Latin), "", "Glavna bb, sprat 2, Vrbica"),
+            (Some(Language::SerbianLatin), None, "", "Главна бб, спрат 2, Врбица"),
+            (None, None, "Street", "спрат 2, Врбица"),
+        ];
+        for (language, script, mask, line) in cases {
+            let options = OutputOptions { date_format: None, expiry_warning_days: 30, script, language, privacy: mask.parse().unwrap() };
+            let json: serde_json::Value = serde_json::from_str(&personal_id.to_json_with(&options, &JsonOptions { pretty: false, photo: false })).unwrap();
+            assert_eq!(json["Address"]["Line"], line);
         }
-        json_output.push_str(&format!("\"Image\": \"{}\"\n", &base64::encode(&self.image)));
-        json_output.push_str("}");
-        json_output
+    }
+
+    #[test]
+    fn rejects_invalid_atr() {
+        for atr in ["3B8", "3BZZ", "3é0", "ЗБ"] {
+            let json = sample().replace(&APOLLO_CARD_TYPE.iter().map(|b| format!("{:02X}", b)).collect::<String>(), atr);
+            let err = PersonalId::from_json(&json).err().unwrap();
+            assert!(err.starts_with("Invalid hex in Atr"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_another_major_version() {
+        let json = sample().replace(&format!("\"{}\"", SCHEMA_VERSION), "\"2.0\"");
+        assert!(PersonalId::from_json(&json).err().unwrap().contains("not supported"));
     }
 }
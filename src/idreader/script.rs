@@ -0,0 +1,149 @@
+use std::str::FromStr;
+
+/// Serbian Cyrillic letters and their Latin spelling, digraphs in title case
+const LETTERS: &[(char, &str)] = &[
+    ('А', "A"), ('Б', "B"), ('В', "V"), ('Г', "G"), ('Д', "D"), ('Ђ', "Đ"), ('Е', "E"), ('Ж', "Ž"),
+    ('З', "Z"), ('И', "I"), ('Ј', "J"), ('К', "K"), ('Л', "L"), ('Љ', "Lj"), ('М', "M"), ('Н', "N"),
+    ('Њ', "Nj"), ('О', "O"), ('П', "P"), ('Р', "R"), ('С', "S"), ('Т', "T"), ('Ћ', "Ć"), ('У', "U"),
+    ('Ф', "F"), ('Х', "H"), ('Ц', "C"), ('Ч', "Č"), ('Џ', "Dž"), ('Ш', "Š"),
+];
+
+fn latin_of(c: char) -> Option<&'static str> {
+    LETTERS.iter().find(|(cyrillic, _)| *cyrillic == c).map(|(_, latin)| *latin)
+}
+
+/// Cyrillic to Latin. Љ, Њ and Џ become LJ, NJ and DŽ in words written in capitals, Lj, Nj and Dž otherwise.
+pub fn to_latin(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let upper = c.to_uppercase().next().unwrap_or(c);
+        let latin = match latin_of(upper) {
+            Some(latin) => latin,
+            None => {
+                out.push(c);
+                continue;
+            }
+        };
+        if c != upper {
+            out.push_str(&latin.to_lowercase());
+        } else if latin.chars().count() > 1 {
+            // A capital digraph is all capitals when the letters around it are
+            let next = chars.get(index + 1).filter(|next| next.is_alphabetic());
+            let previous = index.checked_sub(1).map(|previous| chars[previous]).filter(|previous| previous.is_alphabetic());
+            let capitals = match (next, previous) {
+                (Some(next), _) => next.is_uppercase(),
+                (None, Some(previous)) => previous.is_uppercase(),
+                (None, None) => false,
+            };
+            out.push_str(&if capitals { latin.to_uppercase() } else { latin.to_string() });
+        } else {
+            out.push_str(latin);
+        }
+    }
+    out
+}
+
+/// Latin to Cyrillic. Lj, Nj and Dž are always read as one letter, so the rare words where they are
+/// two, e.g. nadživeti, come out wrong.
+pub fn to_cyrillic(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).map(|next| next.to_lowercase().next().unwrap_or(*next));
+        let digraph = match (c.to_lowercase().next().unwrap_or(c), next) {
+            ('l', Some('j')) => Some('Љ'),
+            ('n', Some('j')) => Some('Њ'),
+            ('d', Some('ž')) => Some('Џ'),
+            _ => None,
+        };
+        let (cyrillic, len) = match digraph {
+            Some(cyrillic) => (Some(cyrillic), 2),
+            None => {
+                let upper: String = c.to_uppercase().collect();
+                (LETTERS.iter().find(|(_, latin)| *latin == upper).map(|(cyrillic, _)| *cyrillic), 1)
+            }
+        };
+        match cyrillic {
+            Some(cyrillic) if c.is_uppercase() => out.push(cyrillic),
+            Some(cyrillic) => out.extend(cyrillic.to_lowercase()),
+            None => out.push(c),
+        }
+        index += len;
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Script {
+    Cyrillic,
+    Latin,
+}
+
+impl Script {
+    pub fn convert(&self, text: &str) -> String {
+        match self {
+            Script::Cyrillic => to_cyrillic(text),
+            Script::Latin => to_latin(text),
+        }
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Script, String> {
+        match value.to_lowercase().as_str() {
+            "cyrillic" | "cyrl" => Ok(Script::Cyrillic),
+            "latin" | "latn" => Ok(Script::Latin),
+            _ => Err(format!("{} is not a script, use cyrillic or latin", value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digraphs_in_capitals() {
+        assert_eq!(to_latin("ЉУБИЦА"), "LJUBICA");
+        assert_eq!(to_latin("ЊЕГОШ"), "NJEGOŠ");
+        assert_eq!(to_latin("ЏЕП"), "DŽEP");
+        assert_eq!(to_latin("КОЊ"), "KONJ");
+    }
+
+    #[test]
+    fn digraphs_in_title_case() {
+        assert_eq!(to_latin("Љубица"), "Ljubica");
+        assert_eq!(to_latin("Његош"), "Njegoš");
+        assert_eq!(to_latin("Џеп"), "Džep");
+        assert_eq!(to_latin("Љ"), "Lj");
+        assert_eq!(to_latin("љиљан"), "ljiljan");
+    }
+
+    #[test]
+    fn latin_digraphs_to_cyrillic() {
+        assert_eq!(to_cyrillic("Ljubica"), "Љубица");
+        assert_eq!(to_cyrillic("LJUBICA"), "ЉУБИЦА");
+        assert_eq!(to_cyrillic("ljiljan"), "љиљан");
+        assert_eq!(to_cyrillic("NJEGOŠ"), "ЊЕГОШ");
+        assert_eq!(to_cyrillic("Džep"), "Џеп");
+    }
+
+    #[test]
+    fn other_text_is_kept() {
+        assert_eq!(to_cyrillic("Đorđe 12, bb"), "Ђорђе 12, бб");
+        assert_eq!(to_latin("Ђорђе 12, бб"), "Đorđe 12, bb");
+        assert_eq!(to_latin("ABC-1"), "ABC-1");
+    }
+
+    #[test]
+    fn parses_script_names() {
+        assert_eq!("Cyrl".parse::<Script>(), Ok(Script::Cyrillic));
+        assert_eq!("latin".parse::<Script>(), Ok(Script::Latin));
+        assert!("greek".parse::<Script>().is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use pcsc::*;
+
+use super::apdu::transmit;
+use super::reader::CardReader;
+
+/// Key used for client authentication
+pub const AUTH_KEY_REFERENCE: u8 = 0x01;
+/// Key used for qualified electronic signatures
+pub const SIGNING_KEY_REFERENCE: u8 = 0x02;
+/// RSA PKCS#1 v1.5, DigestInfo computed off card
+const ALGORITHM_RSA_PKCS1: u8 = 0x02;
+/// Control reference templates for MSE SET
+pub const DIGITAL_SIGNATURE_TEMPLATE: u8 = 0xb6;
+pub const AUTHENTICATION_TEMPLATE: u8 = 0xa4;
+
+pub const SHA256_DIGEST_INFO_PREFIX: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+pub fn sha256_digest_info(digest: &[u8; 32]) -> Vec<u8> {
+    [SHA256_DIGEST_INFO_PREFIX, digest].concat()
+}
+
+/// MSE SET for the given template, selecting key and algorithm
+pub fn set_security_environment(card: &Card, template: u8, key_reference: u8) -> Result<(), String> {
+    let apdu = [0x00, 0x22, 0x41, template, 0x06, 0x80, 0x01, ALGORITHM_RSA_PKCS1, 0x84, 0x01, key_reference];
+    let response = transmit(card, &apdu, false)?;
+    if !response.is_success() {
+        return Err(format!("Reader returned error code at set_security_environment function {:x?}", response.sw));
+    }
+    Ok(())
+}
+
+/// Signs a SHA-256 digest with PSO COMPUTE DIGITAL SIGNATURE.
+/// The PIN has to be verified in the same session beforehand.
+pub fn sign_digest(reader: &dyn CardReader, card: &Card, key_reference: u8, digest: &[u8; 32]) -> Result<Vec<u8>, String> {
+    sign_digest_info(reader, card, key_reference, &sha256_digest_info(digest))
+}
+
+/// Signs an already encoded DigestInfo (or any data shorter than the key), the card adds PKCS#1 v1.5 padding.
+pub fn sign_digest_info(reader: &dyn CardReader, card: &Card, key_reference: u8, digest_info: &[u8]) -> Result<Vec<u8>, String> {
+    reader.select_pki(card)?;
+    set_security_environment(card, DIGITAL_SIGNATURE_TEMPLATE, key_reference)?;
+
+    let apdu: &[u8] = &[0x00, 0x2a, 0x9e, 0x9a, digest_info.len() as u8];
+    let apdu = [apdu, digest_info, &[0x00]].concat();
+    let response = transmit(card, &apdu, true)?;
+    match response.sw {
+        [0x90, 0x00] => Ok(response.data),
+        [0x69, 0x82] => Err("Card refused to sign, the PIN is not verified".to_string()),
+        sw => Err(format!("Reader returned error code at sign_digest function {:x?}", sw)),
+    }
+}
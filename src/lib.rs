@@ -0,0 +1,2 @@
+pub mod idreader;
+pub mod signature;
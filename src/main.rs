@@ -1,9 +1,18 @@
 use std::fs;
-use idreader::reader::{PersonalId, PersonalIdTag};
+use rsid_reader::{idreader, signature};
+use idreader::document::{parse_date, Validity, DATE_TAGS};
+use idreader::json::{JsonId, JsonOptions};
+use idreader::output::{DateFormat, OutputOptions};
+use idreader::labels::{self, Label, Language};
+use idreader::photo::{PhotoFormat, PhotoOptions};
+use idreader::privacy::Privacy;
+use idreader::script::Script;
+use idreader::reader::PersonalId;
+use signature::chain::Verdict;
 use pcsc::*;
-mod idreader;
 use viuer::Config;
 mod pdf;
+mod commands;
 use clap::Parser;
 
 /// Serbian IDCard reader
@@ -14,6 +23,14 @@ struct Args {
     #[clap(short = 'p', long, value_name = "PATH", value_hint = clap::ValueHint::DirPath )]
     to_pdf: Option<String>,
 
+    /// Sign the pdf with the card's qualified key (PAdES)
+    #[clap(long, action, requires = "to-pdf")]
+    sign_pdf: bool,
+
+    /// Sign the pdf with the card in this reader instead, e.g. an operator's card
+    #[clap(long, value_name = "READER", requires = "sign-pdf")]
+    pdf_signer_reader: Option<String>,
+
     /// Dump to JSON to dir path
     #[clap(short = 'j', long, value_name = "PATH", value_hint = clap::ValueHint::DirPath )]
     to_json: Option<String>,
@@ -21,11 +38,184 @@ struct Args {
     /// Dump to JSON to stdout
     #[clap(short = 'o', long, action)]
     to_json_stdout: bool,
+
+    /// Read the data from a JSON written by --to-json instead of the card
+    #[clap(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    from_json: Option<String>,
+
+    /// Write the JSON on one line instead of indented
+    #[clap(long, action)]
+    json_compact: bool,
+
+    /// Leave the photo out of the JSON
+    #[clap(long, action)]
+    json_no_photo: bool,
+
+    /// Date format: iso, local or a strftime pattern. Defaults to iso in JSON, local elsewhere
+    #[clap(long, value_name = "FORMAT")]
+    date_format: Option<DateFormat>,
+
+    /// Report the document as expiring this many days before its expiry date
+    #[clap(long, value_name = "DAYS", default_value = "30")]
+    expiry_warning_days: u32,
+
+    /// Write labels and card values in this script: cyrillic or latin. By default values stay as on the card
+    #[clap(long, value_name = "SCRIPT")]
+    script: Option<Script>,
+
+    /// Write the photo to this file, without metadata
+    #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    photo_out: Option<String>,
+
+    /// Photo format: jpeg or png. Defaults to the file extension, else jpeg
+    #[clap(long, value_name = "FORMAT", requires = "photo-out")]
+    photo_format: Option<PhotoFormat>,
+
+    /// Crop the photo to passport proportions, 35x45 mm
+    #[clap(long, action, requires = "photo-out")]
+    photo_passport: bool,
+
+    /// Scale the photo to this width in pixels
+    #[clap(long, value_name = "PIXELS", requires = "photo-out", value_parser = clap::value_parser!(u32).range(1..))]
+    photo_width: Option<u32>,
+
+    /// Language of the labels: sr-Latn, sr-Cyrl or en. Defaults to the locale
+    #[clap(long, value_name = "LANG")]
+    lang: Option<Language>,
+
+    /// Fields to leave out of all outputs, e.g. jmbg=partial,address,photo
+    #[clap(long, value_name = "FIELDS")]
+    mask: Option<Privacy>,
+
+    /// Same as --mask jmbg=partial,address,photo
+    #[clap(long, action, conflicts_with = "mask")]
+    private: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// PIN status, verification, change and unblocking
+    #[clap(subcommand)]
+    Pin(PinCommand),
+    /// Sign a file with the qualified signature key on the card
+    Sign {
+        /// File to sign
+        #[clap(value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        file: String,
+
+        /// Signature format
+        #[clap(short = 'f', long, value_enum, default_value = "cms")]
+        format: commands::sign::SignatureFormat,
+
+        /// Where to write the detached signature, defaults to FILE.p7s or FILE.sig
+        #[clap(short = 'o', long, value_name = "PATH")]
+        output: Option<String>,
+    },
+    /// Sign an XML document with an enveloped XAdES-BES signature
+    SignXml {
+        /// XML document to sign
+        #[clap(value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        file: String,
+
+        /// Where to write the signed document, defaults to FILE.signed.xml
+        #[clap(short = 'o', long, value_name = "PATH")]
+        output: Option<String>,
+    },
+    /// Verify a detached CMS signature, no card needed
+    Verify {
+        /// Signed file
+        #[clap(value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        file: String,
 
+        /// Detached signature, defaults to FILE.p7s
+        #[clap(short = 's', long, value_name = "PATH")]
+        signature: Option<String>,
+    },
+    /// Print the JSON Schema of the JSON output
+    JsonSchema,
+    /// Check a JMBG and show the date of birth, region and sex it encodes
+    Jmbg {
+        /// The 13 digit personal number
+        #[clap(value_name = "NUMBER")]
+        number: String,
+    },
+    /// Validate the card's certificates against the MUP CAs, offline
+    ///
+    /// Exit code: 0 valid, 2 expired, 3 revoked, 4 untrusted, 5 invalid
+    Certs {
+        /// Additional directory with trusted CA certificates (PEM or DER)
+        #[clap(long, value_name = "DIR", value_hint = clap::ValueHint::DirPath)]
+        ca_dir: Vec<String>,
+
+        /// Don't trust the CA certificates built into the program
+        #[clap(long, action)]
+        no_bundled: bool,
+
+        /// Local CRL file to check revocation against (PEM or DER)
+        #[clap(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        crl: Vec<String>,
+
+        /// Validate this certificate file instead of the card's certificates
+        #[clap(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        cert: Vec<String>,
+    },
+    /// Show ATR, chip production data, card profile and files for diagnostics
+    CardInfo,
+    /// Write the raw eID files as binary and hex with a manifest
+    Dump {
+        /// Directory to write the files to, created if missing
+        #[clap(value_name = "DIR", value_hint = clap::ValueHint::DirPath)]
+        dir: String,
+    },
+    /// List the files that answer SELECT with their size and access conditions
+    Explore {
+        /// File identifiers to try, in hex
+        #[clap(long, value_name = "FIRST-LAST", default_value = "0F00-0FFF")]
+        range: String,
+
+        /// Look in the PKI application instead of the ID application
+        #[clap(long, action)]
+        pki: bool,
+    },
+    /// Prove the card is present with a challenge signed by its authentication key
+    ///
+    /// Exit code: 0 authenticated with a valid certificate, otherwise as for `certs`
+    Authenticate {
+        /// Challenge in hex, at least 16 bytes, a random one by default
+        #[clap(long, value_name = "HEX")]
+        challenge: Option<String>,
+
+        /// Additional directory with trusted CA certificates (PEM or DER)
+        #[clap(long, value_name = "DIR", value_hint = clap::ValueHint::DirPath)]
+        ca_dir: Vec<String>,
+
+        /// Don't trust the CA certificates built into the program
+        #[clap(long, action)]
+        no_bundled: bool,
+
+        /// Local CRL file to check revocation against (PEM or DER)
+        #[clap(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+        crl: Vec<String>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum PinCommand {
+    /// Show how many PIN attempts are left without consuming one
+    Status,
+    /// Verify the PIN entered on the terminal
+    Verify,
+    /// Change the PIN, the current PIN is required
+    Change,
+    /// Set a new PIN using the PUK, also unblocks a blocked PIN
+    Unblock,
+}
+
+/// Connects to the card in the first reader, or the first reader whose name contains `reader_name`.
+fn connect(reader_name: Option<&str>) -> Option<Card> {
     // Establish a PC/SC context.
     let ctx = match Context::establish(Scope::User) {
         Ok(ctx) => ctx,
@@ -45,59 +235,239 @@ fn main() {
         }
     };
 
-    // Use the first reader.
-    let reader = match readers.next() {
-        Some(reader) => reader,
-        None => {
-            println!("No readers are connected.");
-            return;
-        }
+    let reader = match reader_name {
+        Some(name) => match readers.find(|reader| reader.to_string_lossy().contains(name)) {
+            Some(reader) => reader,
+            None => {
+                println!("Reader {} is not connected.", name);
+                return None;
+            }
+        },
+        // Use the first reader.
+        None => match readers.next() {
+            Some(reader) => reader,
+            None => {
+                println!("No readers are connected.");
+                return None;
+            }
+        },
     };
 
     // Connect to the card.
     let result = ctx.connect(reader, ShareMode::Shared, Protocols::ANY);
-    let card = match result {
-        Ok(card) => card,
+    match result {
+        Ok(card) => Some(card),
         Err(Error::NoSmartcard) => {
             println!("A smartcard is not present in the reader.");
-            return;
+            None
         }
         Err(err) => {
             eprintln!("Failed to connect to card: {}", err);
             std::process::exit(1);
         }
+    }
+}
+
+fn exit_with(verdict: Verdict) -> Result<(), String> {
+    if verdict != Verdict::Valid {
+        std::process::exit(verdict.exit_code());
+    }
+    Ok(())
+}
+
+fn print_json_schema() -> Result<(), String> {
+    println!("{}", JsonId::schema());
+    Ok(())
+}
+
+/// Runs `run` with the card in the first reader, does nothing when there is no card
+fn with_card(run: impl FnOnce(&PersonalId, &Card) -> Result<(), String>) -> Result<(), String> {
+    let card = match connect(None) {
+        Some(card) => card,
+        None => return Ok(()),
     };
+    let personal_id = PersonalId::new(&card)?;
+    run(&personal_id, &card)
+}
 
-    let mut personal_id = PersonalId::new(&card).unwrap();
-    personal_id.read_id(&card).unwrap();
+fn run_command(command: Command) -> Result<(), String> {
+    match command {
+        Command::Verify { file, signature } => commands::sign::verify(&file, signature.as_deref()),
+        Command::Jmbg { number } => commands::jmbg::check(&number),
+        Command::JsonSchema => print_json_schema(),
+        Command::Certs { ca_dir, no_bundled, crl, cert } if !cert.is_empty() => {
+            let options = commands::certs::TrustOptions { ca_dirs: &ca_dir, no_bundled, crls: &crl };
+            exit_with(commands::certs::validate_files(&cert, &options)?)
+        }
+        Command::Pin(PinCommand::Status) => with_card(commands::pin::status),
+        Command::Pin(PinCommand::Verify) => with_card(commands::pin::verify),
+        Command::Pin(PinCommand::Change) => with_card(commands::pin::change),
+        Command::Pin(PinCommand::Unblock) => with_card(commands::pin::unblock),
+        Command::Sign { file, format, output } => {
+            with_card(|personal_id, card| commands::sign::sign(personal_id, card, &file, format, output.as_deref()))
+        }
+        Command::SignXml { file, output } => with_card(|personal_id, card| commands::sign::sign_xml(personal_id, card, &file, output.as_deref())),
+        Command::Certs { ca_dir, no_bundled, crl, .. } => with_card(|personal_id, card| {
+            let options = commands::certs::TrustOptions { ca_dirs: &ca_dir, no_bundled, crls: &crl };
+            exit_with(commands::certs::validate_card(personal_id, card, &options)?)
+        }),
+        // Unknown cards are what card-info is for, so it doesn't need a PersonalId
+        Command::CardInfo => connect(None).map_or(Ok(()), |card| commands::card_info::card_info(&card)),
+        Command::Dump { dir } => with_card(|personal_id, card| commands::dump::dump(personal_id, card, &dir)),
+        Command::Explore { range, pki } => with_card(|personal_id, card| commands::explore::explore(personal_id, card, &range, pki)),
+        Command::Authenticate { challenge, ca_dir, no_bundled, crl } => with_card(|personal_id, card| {
+            let options = commands::certs::TrustOptions { ca_dirs: &ca_dir, no_bundled, crls: &crl };
+            exit_with(commands::auth::authenticate(personal_id, card, challenge.as_deref(), &options)?)
+        }),
+    }
+}
 
+/// Signs with `card`, the card the data was read from, unless `signer_reader` is given or the data came from a file.
+/// Then the card in `signer_reader`, or in the first reader, signs.
+fn sign_pdf(personal_id: &PersonalId, card: Option<&Card>, path: &str, options: &OutputOptions, signer_reader: Option<&str>) -> Result<(), String> {
+    match (signer_reader, card) {
+        (None, Some(card)) => pdf::topdf_with(personal_id, path, options, |pdf| commands::sign::sign_pdf(personal_id, card, &pdf)),
+        (name, _) => {
+            let signer_card = connect(name).ok_or_else(|| format!("No card to sign with in reader {}", name.unwrap_or_default()))?;
+            let signer = PersonalId::new(&signer_card)?;
+            pdf::topdf_with(personal_id, path, options, |pdf| commands::sign::sign_pdf(&signer, &signer_card, &pdf))
+        }
+    }
+}
 
-    if args.to_pdf == None && args.to_json == None && !args.to_json_stdout {
-        let conf = Config { absolute_offset:false, x: 0, y: 0, width: Some(42), height: Some(28), ..Default::default()};
-        let img = image::load_from_memory(&personal_id.image).expect("Could not read image");
+fn load_json(path: &str) -> Result<PersonalId, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    PersonalId::from_json(&text)
+}
 
-        viuer::print(&img, &conf).expect("Image printing failed");
+fn main() {
+    let args = Args::parse();
+
+    if let Some(command) = args.command {
+        if let Err(err) = run_command(command) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (personal_id, card) = match &args.from_json {
+        Some(path) => match load_json(path) {
+            Ok(personal_id) => (personal_id, None),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let card = match connect(None) {
+                Some(card) => card,
+                None => return,
+            };
+            let mut personal_id = PersonalId::new(&card).unwrap();
+            personal_id.read_id(&card).unwrap();
+            (personal_id, Some(card))
+        }
+    };
+    for section in &personal_id.sections {
+        if let Some(error) = &section.error {
+            eprintln!("Warning: {} file not read: {}", section.name, error);
+        }
+    }
+    let options = OutputOptions {
+        date_format: args.date_format,
+        expiry_warning_days: args.expiry_warning_days as i64,
+        script: args.script,
+        // An explicit script picks the Serbian labels in it over the locale
+        language: args.lang.or_else(|| if args.script.is_none() { Language::from_locale() } else { None }),
+        privacy: args.mask.unwrap_or_else(|| if args.private { Privacy::standard() } else { Privacy::default() }),
+    };
+    let json_options = JsonOptions { pretty: !args.json_compact, photo: !args.json_no_photo };
+    let document = options.document(&personal_id);
+    for error in &document.errors {
+        eprintln!("Warning: {}", error);
+    }
+    let validity = document.document.validity_today(options.expiry_warning_days);
+    let terminal = args.to_pdf.is_none() && args.to_json.is_none() && !args.to_json_stdout && args.photo_out.is_none();
+    if !terminal && !matches!(validity, Validity::Valid { .. }) {
+        let language = options.language(Language::English);
+        eprintln!("Warning: {}: {}", labels::text(Label::DocumentStatus, language), labels::validity(validity, language));
+    }
+
+
+    if terminal {
+        if !document.photo.data.is_empty() {
+            let conf = Config { absolute_offset:false, x: 0, y: 0, width: Some(42), height: Some(28), ..Default::default()};
+            let printed = image::load_from_memory(&document.photo.data)
+                .map_err(|err| format!("Photo can't be decoded: {}", err))
+                .and_then(|img| viuer::print(&img, &conf).map_err(|err| format!("Photo can't be shown: {}", err)));
+            if let Err(err) = printed {
+                eprintln!("Warning: {}", err);
+            }
+        }
+        let date_format = options.date_format(DateFormat::Local);
+        let language = options.language(Language::SerbianLatin);
+        let status = labels::text(Label::DocumentStatus, language);
+        match validity {
+            Validity::Valid { .. } => println!("{: >20}: {}", status, labels::validity(validity, language)),
+            _ => println!("{: >20}: *** {} ***", status, labels::validity(validity, language).to_uppercase()),
+        }
         for (_tag, item) in personal_id.personal.iter() {
-            println!("{}", item);
+            let masked = match options.privacy.apply(item.tag.id, &item.value) {
+                Some(masked) => masked,
+                None => continue,
+            };
+            let date = DATE_TAGS.contains(&item.tag.id).then(|| parse_date(&masked));
+            let value = match date {
+                Some(Ok(date)) => date_format.format(date),
+                Some(Err(_)) => format!("{} ({})", masked, labels::text(Label::InvalidDate, language)),
+                None => options.text(&masked),
+            };
+            println!("{: >20}: {}", labels::text(Label::Field(item.tag.id), language), value);
+        }
+        for (index, line) in options.address_lines(&document.address, language).iter().enumerate() {
+            let label = if index == 0 { labels::text(Label::Address, language) } else { "" };
+            println!("{: >20}{} {}", label, if index == 0 { ":" } else { " " }, line);
         }
     }
 
     if let Some(path) = args.to_json {
         if !path.is_empty() {
-            if let Some(personal_number) = personal_id.personal.get(&PersonalIdTag::PersonalNumber) {
-                fs::write(&[path, personal_number.value.clone(), ".json".to_string()].concat(), personal_id.to_json()).expect("Unable to write file");
-            }
+            fs::write([path.as_str(), &options.file_stem(&personal_id), ".json"].concat(), personal_id.to_json_with(&options, &json_options)).expect("Unable to write file");
         }
     }
 
     if args.to_json_stdout {
-        println!("{}", personal_id.to_json());
+        println!("{}", personal_id.to_json_with(&options, &json_options));
+    }
+
+    if let Some(path) = args.photo_out {
+        let format = args.photo_format.or_else(|| PhotoFormat::from_path(&path)).unwrap_or(PhotoFormat::Jpeg);
+        let photo_options = PhotoOptions { format, passport: args.photo_passport, width: args.photo_width };
+        if document.photo.data.is_empty() {
+            eprintln!("No photo to write to {}: it is masked, was left out of the JSON or could not be read from the card", path);
+            std::process::exit(1);
+        }
+        let written = document.photo.export(&photo_options)
+            .and_then(|photo| fs::write(&path, photo).map_err(|err| format!("{}: {}", path, err)));
+        if let Err(err) = written {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
     }
 
     if let Some(path) = args.to_pdf {
         if !path.is_empty() {
             pdf::copy_font();
-            pdf::topdf(&personal_id, &path).unwrap();            
+            if args.sign_pdf {
+                if let Err(err) = sign_pdf(&personal_id, card.as_ref(), &path, &options, args.pdf_signer_reader.as_deref()) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            } else if let Err(err) = pdf::topdf(&personal_id, &path, &options) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
         }
     }
 
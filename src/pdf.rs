@@ -2,9 +2,24 @@ extern crate printpdf;
 use printpdf::*;
 use std::convert::From;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::Write;
+use chrono::NaiveDate;
+use crate::idreader::document::{IdentityDocument, Validity};
+use crate::idreader::labels::{self, Label, Language};
+use crate::idreader::output::{DateFormat, OutputOptions};
 use crate::idreader::reader::*;
 
+/// A date the card holds that doesn't parse is shown as it is, marked as invalid
+fn date(tag: PersonalIdTag, date: Option<NaiveDate>, id: &IdentityDocument, format: &DateFormat, language: Language) -> String {
+    match date {
+        Some(date) => format.format(date),
+        None => match id.errors.iter().find(|error| error.tag == tag) {
+            Some(error) => format!("{} ({})", error.value, labels::text(Label::InvalidDate, language)),
+            None => String::new(),
+        },
+    }
+}
+
 fn add_line(x: f64, y: f64, current_layer: &PdfLayerReference) {
     let points1 = vec![
     (Point::new(Mm(x), Mm(y)), false),
@@ -45,8 +60,6 @@ fn add_image(x: f64, y: f64, buffer: &[u8], current_layer: &PdfLayerReference) -
 
     let fill_color = Color::Cmyk(Cmyk::new(0.0, 0.0, 0.0, 0.0, None));
     let outline_color = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
-    let mut dash_pattern = LineDashPattern::default();
-    dash_pattern.dash_1 = Some(20);
 
     current_layer.set_fill_color(fill_color);
     current_layer.set_outline_color(outline_color);
@@ -54,8 +67,11 @@ fn add_image(x: f64, y: f64, buffer: &[u8], current_layer: &PdfLayerReference) -
 
     current_layer.add_shape(line1);
 
-
-    let dyn_image = image_crate::load_from_memory(buffer).or_else(|err| return Err(err.to_string()))?;
+    // Left empty when the photo is masked
+    if buffer.is_empty() {
+        return Ok(());
+    }
+    let dyn_image = image_crate::load_from_memory(buffer).map_err(|err| err.to_string())?;
     let ximage = ImageXObject::from_dynamic_image(&dyn_image);
     let image = Image::from(ximage);
     image.add_to_layer(
@@ -75,13 +91,13 @@ fn add_image(x: f64, y: f64, buffer: &[u8], current_layer: &PdfLayerReference) -
 fn add_text(x:f64, y:f64, text: &str, font_size: f64, font: &IndirectFontRef, current_layer: &PdfLayerReference) {
     current_layer.begin_text_section();
 
-    current_layer.set_font(&font, font_size);
+    current_layer.set_font(font, font_size);
     current_layer.set_text_cursor(Mm(x), Mm(y));
     current_layer.set_line_height(5.0);
     current_layer.set_word_spacing(5.0);
     current_layer.set_character_spacing(0.3);
     current_layer.set_text_rendering_mode(TextRenderingMode::Fill);
-    current_layer.write_text(text, &font);
+    current_layer.write_text(text, font);
     current_layer.add_line_break();
 
     current_layer.end_text_section();
@@ -96,7 +112,15 @@ pub fn copy_font() {
 
 }
 
-pub fn topdf(personal_id: &PersonalId, path: &str) -> Result<(), String>{
+pub fn topdf(personal_id: &PersonalId, path: &str, options: &OutputOptions) -> Result<(), String> {
+    topdf_with(personal_id, path, options, Ok)
+}
+
+/// Same as `topdf`, but `finish` gets the rendered PDF and returns the bytes to write, e.g. signed.
+pub fn topdf_with<F>(personal_id: &PersonalId, path: &str, options: &OutputOptions, finish: F) -> Result<(), String>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, String>,
+{
     let (doc, page1, layer1) =
         PdfDocument::new("Podaci licne karte", Mm(210.0), Mm(297.0), "Layer 1");
     let current_layer = doc.get_page(page1).get_layer(layer1);
@@ -113,85 +137,87 @@ pub fn topdf(personal_id: &PersonalId, path: &str) -> Result<(), String>{
     };
 
 
-    let empty_item = PersonalIdItem::default();
-
-    let surname = &personal_id.personal.get(&PersonalIdTag::Surname).unwrap_or(&empty_item).value;
-    let name = &personal_id.personal.get(&PersonalIdTag::GivenName).unwrap_or(&empty_item).value;
-    let birthdate = &personal_id.personal.get(&PersonalIdTag::DateOfBirth).unwrap_or(&empty_item).value;
-    let place_of_birth = &personal_id.personal.get(&PersonalIdTag::PlaceOfBirth).unwrap_or(&empty_item).value;
-    let state_of_birth = &personal_id.personal.get(&PersonalIdTag::StateOfBirth).unwrap_or(&empty_item).value;
-    let community_of_birth = &personal_id.personal.get(&PersonalIdTag::CommunityOfBirth).unwrap_or(&empty_item).value;
-    let parent = &personal_id.personal.get(&PersonalIdTag::ParentGivenName).unwrap_or(&empty_item).value;
-    let state = &personal_id.personal.get(&PersonalIdTag::State).unwrap_or(&empty_item).value;
-    let community = &personal_id.personal.get(&PersonalIdTag::Community).unwrap_or(&empty_item).value;
-    let address = &personal_id.personal.get(&PersonalIdTag::Street).unwrap_or(&empty_item).value;
-    let house_number = &personal_id.personal.get(&PersonalIdTag::HouseNumber).unwrap_or(&empty_item).value;
-    let place = &personal_id.personal.get(&PersonalIdTag::Place).unwrap_or(&empty_item).value;
-    let personal_number = &personal_id.personal.get(&PersonalIdTag::PersonalNumber).unwrap_or(&empty_item).value;
-    let gender = &personal_id.personal.get(&PersonalIdTag::Sex).unwrap_or(&empty_item).value;
-    let authority = &personal_id.personal.get(&PersonalIdTag::IssuingAuthority).unwrap_or(&empty_item).value;
-    let id_no = &personal_id.personal.get(&PersonalIdTag::DocRegNo).unwrap_or(&empty_item).value;
-    let issuing_date = &personal_id.personal.get(&PersonalIdTag::IssuingDate).unwrap_or(&empty_item).value;
-    let expiry_date = &personal_id.personal.get(&PersonalIdTag::ExpiryDate).unwrap_or(&empty_item).value;
+    let id = options.document(personal_id);
+    let person = &id.person;
+    let document = &id.document;
+    let address = &id.address;
+    let language = options.language(Language::SerbianCyrillic);
+    let text = |x: f64, y: f64, value: &str, font_size: f64| add_text(x, y, &options.text(value), font_size, &font2, &current_layer);
+    let heading = |x: f64, y: f64, label: Label, font_size: f64| add_text(x, y, labels::text(label, language), font_size, &font2, &current_layer);
+    let label = |y: f64, label: Label| add_text(left_margin+2.0, y, &format!("{}:", labels::text(label, language)), 11.0, &font2, &current_layer);
+    let date_format = options.date_format(DateFormat::Local);
+    let date = |tag, value| date(tag, value, &id, &date_format, language);
+    let birthdate = &date(PersonalIdTag::DateOfBirth, person.date_of_birth);
+    let residence = options.address_lines(address, language);
+    let address_date = &date(PersonalIdTag::AddressDate, address.address_date);
+    let gender = person.sex.map(|sex| labels::sex(sex, language)).unwrap_or_default();
+    let issuing_date = &date(PersonalIdTag::IssuingDate, document.issuing_date);
+    let expiry_date = &date(PersonalIdTag::ExpiryDate, document.expiry_date);
+    let validity = match document.validity_today(options.expiry_warning_days) {
+        validity @ Validity::Expired { .. } => labels::validity(validity, language).to_uppercase(),
+        validity => labels::validity(validity, language),
+    };
 
     add_line(left_margin, 277.0, &current_layer);
-    add_text(left_margin+2.0, 269.0, "ЧИТАЧ ЕЛЕКТРОНСКЕ ЛИЧНЕ КАРТЕ: ШТАМПА ПОДАТАКА", 15.5, &font2, &current_layer);
+    heading(left_margin+2.0, 269.0, Label::Title, 15.5);
     add_line(left_margin, 265.0, &current_layer);
 
     add_line(left_margin, 196.0, &current_layer);
-    add_text(left_margin+2.0, 190.0, "Подаци о грађанину", 12.0, &font2, &current_layer);
+    heading(left_margin+2.0, 190.0, Label::CitizenData, 12.0);
     add_line(left_margin, 187.0, &current_layer);
 
-    add_text(left_margin+2.0, 181.0, "Презиме:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 181.0, surname, 11.0, &font2, &current_layer);
+    label(181.0, Label::Field(PersonalIdTag::Surname));
+    text(left_margin+data_margin, 181.0, &person.surname, 11.0);
 
-    add_text(left_margin+2.0, 173.0, "Име:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 173.0, name, 11.0, &font2, &current_layer);
+    label(173.0, Label::Field(PersonalIdTag::GivenName));
+    text(left_margin+data_margin, 173.0, &person.given_name, 11.0);
 
-    add_text(left_margin+2.0, 165.0, "Име једног родитеља:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 165.0, parent, 11.0, &font2, &current_layer);
+    label(165.0, Label::ParentName);
+    text(left_margin+data_margin, 165.0, &person.parent_given_name, 11.0);
 
-    add_text(left_margin+2.0, 157.0, "Датум рођења:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 157.0, birthdate, 11.0, &font2, &current_layer);
+    label(157.0, Label::Field(PersonalIdTag::DateOfBirth));
+    text(left_margin+data_margin, 157.0, birthdate, 11.0);
 
-    add_text(left_margin+2.0, 148.0, "Место рођења,", 11.0, &font2, &current_layer);
-    add_text(left_margin+2.0, 144.0, "општина и држава:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 146.0, &[place_of_birth,",",community_of_birth,",",state_of_birth].to_vec().concat(), 11.0, &font2, &current_layer);
+    heading(left_margin+2.0, 148.0, Label::BirthPlaceFirstLine, 11.0);
+    label(144.0, Label::BirthPlaceSecondLine);
+    text(left_margin+data_margin, 146.0, &[person.place_of_birth.as_str(), &person.community_of_birth, &person.state_of_birth].join(","), 11.0);
+
+    heading(left_margin+2.0, 135.0, Label::ResidenceFirstLine, 11.0);
+    label(131.0, Label::ResidenceSecondLine);
+    for (index, line) in residence.iter().enumerate() {
+        add_text(left_margin+data_margin, 135.0 - 4.0 * index as f64, line, 11.0, &font2, &current_layer);
+    }
 
-    add_text(left_margin+2.0, 135.0, "Пребивалиште и", 11.0, &font2, &current_layer);
-    add_text(left_margin+2.0, 131.0, "адреса стана:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 133.0, &[address,",",house_number,",",community,",", place, ",", state].to_vec().concat(), 11.0, &font2, &current_layer);
+    label(122.0, Label::Field(PersonalIdTag::AddressDate));
+    text(left_margin+data_margin, 122.0, address_date, 11.0);
 
-    add_text(left_margin+2.0, 121.0, "ЈМБГ:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 121.0, personal_number, 11.0, &font2, &current_layer);
+    label(116.0, Label::Field(PersonalIdTag::PersonalNumber));
+    text(left_margin+data_margin, 116.0, &person.personal_number, 11.0);
 
-    add_text(left_margin+2.0, 111.0, "Пол:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 111.0, gender, 11.0, &font2, &current_layer);
+    label(110.0, Label::Field(PersonalIdTag::Sex));
+    add_text(left_margin+data_margin, 110.0, gender, 11.0, &font2, &current_layer);
 
     add_line(left_margin, 107.0, &current_layer);
-    add_text(left_margin+2.0, 101.5, "Подаци о документу", 12.0, &font2, &current_layer);
+    heading(left_margin+2.0, 101.5, Label::DocumentData, 12.0);
     add_line(left_margin, 98.0, &current_layer);
 
-    add_text(left_margin+2.0, 91.0, "Документ издаје:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 91.0, authority, 11.0, &font2, &current_layer);
+    label(91.0, Label::IssuedBy);
+    text(left_margin+data_margin, 91.0, &document.issuing_authority, 11.0);
 
-    add_text(left_margin+2.0, 83.0, "Број документа:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 83.0, id_no, 11.0, &font2, &current_layer);
+    label(83.0, Label::Field(PersonalIdTag::DocRegNo));
+    text(left_margin+data_margin, 83.0, &document.number, 11.0);
 
-    add_text(left_margin+2.0, 75.0, "Датум издавања:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 75.0, issuing_date, 11.0, &font2, &current_layer);
+    label(75.0, Label::Field(PersonalIdTag::IssuingDate));
+    text(left_margin+data_margin, 75.0, issuing_date, 11.0);
 
-    add_text(left_margin+2.0, 67.0, "Важи до:", 11.0, &font2, &current_layer);
-    add_text(left_margin+data_margin, 67.0, expiry_date, 11.0, &font2, &current_layer);
+    label(67.0, Label::ValidUntil);
+    text(left_margin+data_margin, 67.0, expiry_date, 11.0);
 
-    add_image(left_margin, 203.0, &personal_id.image, &current_layer).unwrap();
-    let pdf_file = match File::create(&[path,"/",personal_number,".pdf"].concat()) {
-        Ok(file) => file,
-        Err(err) => return Err(err.to_string())
-    };
+    label(59.0, Label::DocumentStatus);
+    add_text(left_margin+data_margin, 59.0, &validity, 11.0, &font2, &current_layer);
 
-    match doc.save(&mut BufWriter::new(pdf_file)) {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string())
-    }
+    add_image(left_margin, 203.0, &id.photo.data, &current_layer).map_err(|err| format!("Photo can't be decoded: {}", err))?;
+    let bytes = doc.save_to_bytes().map_err(|err| err.to_string())?;
+    let bytes = finish(bytes)?;
+    std::fs::write([path, "/", &options.file_stem(personal_id), ".pdf"].concat(), bytes).map_err(|err| err.to_string())
 }
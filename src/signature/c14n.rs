@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+pub enum Node {
+    Element(Element),
+    Text(String),
+    ProcessingInstruction(String),
+}
+
+pub struct Element {
+    pub name: String,
+    /// Attributes in document order, namespace declarations included
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+pub struct Document {
+    pub prolog: Vec<Node>,
+    pub root: Element,
+    pub epilog: Vec<Node>,
+    /// Byte range of the root end tag in the source, or of `/>` for an empty root
+    pub root_end: Range<usize>,
+}
+
+fn prefix(name: &str) -> &str {
+    name.split_once(':').map(|(prefix, _)| prefix).unwrap_or("")
+}
+
+fn local_name(name: &str) -> &str {
+    name.split_once(':').map(|(_, local)| local).unwrap_or(name)
+}
+
+fn parse_element(start: &BytesStart) -> Result<Element, String> {
+    let name = String::from_utf8(start.name().as_ref().to_vec()).map_err(|err| err.to_string())?;
+    let mut attributes = vec![];
+    for attr in start.attributes() {
+        let attr = attr.map_err(|err| err.to_string())?;
+        let key = String::from_utf8(attr.key.as_ref().to_vec()).map_err(|err| err.to_string())?;
+        let raw = std::str::from_utf8(&attr.value).map_err(|err| err.to_string())?;
+        // Attribute value normalization, character references survive it
+        let raw = raw.replace(['\t', '\n', '\r'], " ");
+        let value = unescape(&raw).map_err(|err| err.to_string())?.into_owned();
+        attributes.push((key, value));
+    }
+    Ok(Element { name, attributes, children: vec![] })
+}
+
+fn push_node(stack: &mut [Element], outside: &mut Vec<Node>, node: Node) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => outside.push(node),
+    }
+}
+
+pub fn parse(xml: &str) -> Result<Document, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = vec![];
+    let mut prolog = vec![];
+    let mut epilog = vec![];
+    let mut root: Option<(Element, Range<usize>)> = None;
+
+    loop {
+        let outside = if root.is_none() { &mut prolog } else { &mut epilog };
+        match reader.read_event().map_err(|err| format!("Invalid XML at {}: {}", reader.buffer_position(), err))? {
+            Event::Start(start) => {
+                if root.is_some() {
+                    return Err("XML has more than one root element".to_string());
+                }
+                stack.push(parse_element(&start)?);
+            }
+            Event::Empty(start) => {
+                let element = parse_element(&start)?;
+                if stack.is_empty() {
+                    if root.is_some() {
+                        return Err("XML has more than one root element".to_string());
+                    }
+                    let end = reader.buffer_position();
+                    root = Some((element, end - 2..end));
+                } else {
+                    push_node(&mut stack, outside, Node::Element(element));
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or("Unexpected end tag")?;
+                if stack.is_empty() {
+                    let end = reader.buffer_position();
+                    let start = xml[..end].rfind("</").ok_or("Unexpected end tag")?;
+                    root = Some((element, start..end));
+                } else {
+                    push_node(&mut stack, outside, Node::Element(element));
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|err| err.to_string())?.into_owned();
+                if !stack.is_empty() {
+                    push_node(&mut stack, outside, Node::Text(text));
+                } else if !text.trim().is_empty() {
+                    return Err("XML has text outside of the root element".to_string());
+                }
+            }
+            Event::CData(text) => {
+                let text = String::from_utf8(text.into_inner().into_owned()).map_err(|err| err.to_string())?;
+                push_node(&mut stack, outside, Node::Text(text));
+            }
+            Event::PI(pi) => {
+                let pi = String::from_utf8(pi.into_inner().into_owned()).map_err(|err| err.to_string())?;
+                push_node(&mut stack, outside, Node::ProcessingInstruction(pi));
+            }
+            Event::DocType(_) => return Err("XML documents with a DTD are not supported".to_string()),
+            Event::Decl(_) | Event::Comment(_) => {}
+            Event::Eof => break,
+        }
+    }
+
+    let (root, root_end) = root.ok_or("XML has no root element")?;
+    Ok(Document { prolog, root, epilog, root_end })
+}
+
+fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_pi(pi: &str, out: &mut String) {
+    out.push_str("<?");
+    match pi.split_once(char::is_whitespace) {
+        Some((target, data)) if !data.trim_start().is_empty() => {
+            out.push_str(target);
+            out.push(' ');
+            out.push_str(data.trim_start());
+        }
+        Some((target, _)) => out.push_str(target),
+        None => out.push_str(pi),
+    }
+    out.push_str("?>");
+}
+
+fn write_element(element: &Element, in_scope: &HashMap<String, String>, rendered: &HashMap<String, String>, out: &mut String) {
+    let mut in_scope = in_scope.clone();
+    for (key, value) in &element.attributes {
+        if key == "xmlns" {
+            in_scope.insert(String::new(), value.clone());
+        } else if let Some(declared) = key.strip_prefix("xmlns:") {
+            in_scope.insert(declared.to_string(), value.clone());
+        }
+    }
+    let attributes: Vec<&(String, String)> = element.attributes.iter()
+        .filter(|(key, _)| key != "xmlns" && !key.starts_with("xmlns:"))
+        .collect();
+
+    // Exclusive canonicalization only renders namespaces the element or its attributes actually use
+    let mut used = vec![prefix(&element.name)];
+    for (key, _) in &attributes {
+        if key.contains(':') && prefix(key) != "xml" && !used.contains(&prefix(key)) {
+            used.push(prefix(key));
+        }
+    }
+    let mut rendered = rendered.clone();
+    let mut declarations = vec![];
+    for used_prefix in used {
+        let uri = in_scope.get(used_prefix).cloned().unwrap_or_default();
+        let already = rendered.get(used_prefix).cloned().unwrap_or_default();
+        if uri != already || (!used_prefix.is_empty() && !rendered.contains_key(used_prefix)) {
+            declarations.push((used_prefix.to_string(), uri.clone()));
+            rendered.insert(used_prefix.to_string(), uri);
+        }
+    }
+    declarations.sort();
+
+    let namespace_of = |key: &str| -> String {
+        match prefix(key) {
+            "" => String::new(),
+            "xml" => XML_NAMESPACE.to_string(),
+            prefix => in_scope.get(prefix).cloned().unwrap_or_default(),
+        }
+    };
+    let mut attributes: Vec<(String, &str, &str)> = attributes.iter()
+        .map(|(key, value)| (namespace_of(key), key.as_str(), value.as_str()))
+        .collect();
+    attributes.sort_by(|a, b| (&a.0, local_name(a.1)).cmp(&(&b.0, local_name(b.1))));
+
+    out.push('<');
+    out.push_str(&element.name);
+    for (declared, uri) in &declarations {
+        if declared.is_empty() {
+            out.push_str(" xmlns=\"");
+        } else {
+            out.push_str(" xmlns:");
+            out.push_str(declared);
+            out.push_str("=\"");
+        }
+        escape_attribute(uri, out);
+        out.push('"');
+    }
+    for (_, key, value) in &attributes {
+        out.push(' ');
+        out.push_str(key);
+        out.push_str("=\"");
+        escape_attribute(value, out);
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in &element.children {
+        match child {
+            Node::Element(child) => write_element(child, &in_scope, &rendered, out),
+            Node::Text(text) => escape_text(text, out),
+            Node::ProcessingInstruction(pi) => write_pi(pi, out),
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&element.name);
+    out.push('>');
+}
+
+/// Exclusive XML Canonicalization 1.0 without comments of a whole document.
+pub fn canonicalize(document: &Document) -> String {
+    let mut out = String::new();
+    for node in &document.prolog {
+        if let Node::ProcessingInstruction(pi) = node {
+            write_pi(pi, &mut out);
+            out.push('\n');
+        }
+    }
+    write_element(&document.root, &HashMap::new(), &HashMap::new(), &mut out);
+    for node in &document.epilog {
+        if let Node::ProcessingInstruction(pi) = node {
+            out.push('\n');
+            write_pi(pi, &mut out);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Inputs and outputs from the examples in the W3C Canonical XML and Exclusive XML
+    // Canonicalization recommendations, DTDs and the parts that depend on them removed.
+
+    fn canonical(xml: &str) -> String {
+        canonicalize(&parse(xml).unwrap())
+    }
+
+    #[test]
+    fn processing_instructions_and_comments() {
+        let xml = concat!(
+            "<?xml version=\"1.0\"?>\n\n",
+            "<?xml-stylesheet   href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\n",
+            "<doc>Hello, world!<!-- Comment 1 --></doc>\n\n",
+            "<?pi-without-data     ?>\n\n",
+            "<!-- Comment 2 -->\n\n",
+            "<!-- Comment 3 -->\n",
+        );
+        assert_eq!(canonical(xml), concat!(
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+            "<doc>Hello, world!</doc>\n",
+            "<?pi-without-data?>",
+        ));
+    }
+
+    #[test]
+    fn start_and_end_tags() {
+        let xml = concat!(
+            "<doc>\n",
+            "   <e1   />\n",
+            "   <e2   ></e2>\n",
+            "   <e3   name = \"elem3\"   id=\"elem3\"   />\n",
+            "   <e4   name=\"elem4\"   id=\"elem4\"   ></e4>\n",
+            "   <e5 a:attr=\"out\" b:attr=\"sorted\" attr2=\"all\" attr=\"I'm\"\n",
+            "      xmlns:b=\"http://www.ietf.org\"\n",
+            "      xmlns:a=\"http://www.w3.org\"\n",
+            "      xmlns=\"http://example.org\"/>\n",
+            "   <e6 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n",
+            "      <e7 xmlns=\"http://www.ietf.org\">\n",
+            "         <e8 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n",
+            "            <e9 xmlns=\"\" xmlns:a=\"http://www.ietf.org\"/>\n",
+            "         </e8>\n",
+            "      </e7>\n",
+            "   </e6>\n",
+            "</doc>",
+        );
+        assert_eq!(canonical(xml), concat!(
+            "<doc>\n",
+            "   <e1></e1>\n",
+            "   <e2></e2>\n",
+            "   <e3 id=\"elem3\" name=\"elem3\"></e3>\n",
+            "   <e4 id=\"elem4\" name=\"elem4\"></e4>\n",
+            "   <e5 xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\" xmlns:b=\"http://www.ietf.org\" ",
+            "attr=\"I'm\" attr2=\"all\" b:attr=\"sorted\" a:attr=\"out\"></e5>\n",
+            "   <e6>\n",
+            "      <e7 xmlns=\"http://www.ietf.org\">\n",
+            "         <e8 xmlns=\"\">\n",
+            "            <e9></e9>\n",
+            "         </e8>\n",
+            "      </e7>\n",
+            "   </e6>\n",
+            "</doc>",
+        ));
+    }
+
+    #[test]
+    fn character_modifications() {
+        let xml = concat!(
+            "<doc>\n",
+            "   <text>First line&#x0d;&#10;Second line</text>\n",
+            "   <value>&#x32;</value>\n",
+            "   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>\n",
+            "   <compute expr='value>\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"'>valid</compute>\n",
+            "   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>\n",
+            "</doc>",
+        );
+        assert_eq!(canonical(xml), concat!(
+            "<doc>\n",
+            "   <text>First line&#xD;\nSecond line</text>\n",
+            "   <value>2</value>\n",
+            "   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>\n",
+            "   <compute expr=\"value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;\">valid</compute>\n",
+            "   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>\n",
+            "</doc>",
+        ));
+    }
+
+    #[test]
+    fn only_visibly_used_namespaces_are_rendered() {
+        let xml = concat!(
+            "<n0:local xmlns:n0=\"foo:bar\" xmlns:n3=\"ftp://example.org\">\n",
+            "  <n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n",
+            "     <n3:stuff xmlns:n3=\"ftp://example.org\"/>\n",
+            "  </n1:elem2>\n",
+            "</n0:local>",
+        );
+        assert_eq!(canonical(xml), concat!(
+            "<n0:local xmlns:n0=\"foo:bar\">\n",
+            "  <n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">\n",
+            "     <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n",
+            "  </n1:elem2>\n",
+            "</n0:local>",
+        ));
+    }
+
+    #[test]
+    fn root_end_covers_the_end_tag() {
+        let xml = "<a><b/></a>\n";
+        assert_eq!(&xml[parse(xml).unwrap().root_end], "</a>");
+        let xml = "<a x=\"1\"/>";
+        assert_eq!(&xml[parse(xml).unwrap().root_end], "/>");
+    }
+
+    #[test]
+    fn dtd_is_rejected() {
+        assert!(parse("<!DOCTYPE doc [<!ENTITY e \"x\">]><doc>&e;</doc>").is_err());
+    }
+}
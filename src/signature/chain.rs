@@ -0,0 +1,397 @@
+use std::fmt;
+use std::fs;
+use std::time::SystemTime;
+use const_oid::db::rfc5912::{
+    SHA_1_WITH_RSA_ENCRYPTION, SHA_256_WITH_RSA_ENCRYPTION, SHA_384_WITH_RSA_ENCRYPTION, SHA_512_WITH_RSA_ENCRYPTION,
+};
+use der::{Decode, Encode};
+use rsa::Pkcs1v15Sign;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use x509_cert::crl::CertificateList;
+use x509_cert::ext::pkix::{BasicConstraints, KeyUsage};
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use x509_cert::Certificate;
+
+use super::rsa_public_key;
+
+/// MUP root and intermediate CA certificates, see certs/mup-ca.pem
+const BUNDLED_CA: &str = include_str!("../../certs/mup-ca.pem");
+const MAX_CHAIN_LENGTH: usize = 5;
+
+/// Ordered from best to worst, so the worst verdict of several certificates is the maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Valid,
+    Expired,
+    Revoked,
+    Untrusted,
+    Invalid,
+}
+
+impl Verdict {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Verdict::Valid => 0,
+            Verdict::Expired => 2,
+            Verdict::Revoked => 3,
+            Verdict::Untrusted => 4,
+            Verdict::Invalid => 5,
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Valid => write!(f, "valid"),
+            Verdict::Expired => write!(f, "expired"),
+            Verdict::Revoked => write!(f, "revoked"),
+            Verdict::Untrusted => write!(f, "untrusted"),
+            Verdict::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum KeyPurpose {
+    Signing,
+    Authentication,
+}
+
+pub struct Validation {
+    pub verdict: Verdict,
+    pub reason: Option<String>,
+    /// Leaf first, as far as the chain could be built
+    pub chain: Vec<Certificate>,
+}
+
+impl Validation {
+    fn fail(verdict: Verdict, reason: String, chain: Vec<Certificate>) -> Validation {
+        Validation { verdict, reason: Some(reason), chain }
+    }
+}
+
+/// Reads PEM (text around the blocks is ignored) or a single DER certificate.
+pub fn load_certificates(bytes: &[u8]) -> Result<Vec<Certificate>, String> {
+    let pem_start = bytes.windows(10).position(|window| window == b"-----BEGIN");
+    match pem_start {
+        Some(start) => Certificate::load_pem_chain(&bytes[start..]).map_err(|err| err.to_string()),
+        None if bytes.iter().all(u8::is_ascii) => Ok(vec![]),
+        None => Ok(vec![Certificate::from_der(bytes).map_err(|err| err.to_string())?]),
+    }
+}
+
+fn load_crl(bytes: &[u8]) -> Result<CertificateList, String> {
+    if bytes.starts_with(b"-----BEGIN") {
+        let (_, der) = der::pem::decode_vec(bytes).map_err(|err| err.to_string())?;
+        CertificateList::from_der(&der).map_err(|err| err.to_string())
+    } else {
+        CertificateList::from_der(bytes).map_err(|err| err.to_string())
+    }
+}
+
+fn verify_signature(tbs: &[u8], algorithm: &AlgorithmIdentifierOwned, signature: &[u8], issuer: &Certificate) -> Result<(), String> {
+    let key = rsa_public_key(issuer)?;
+    let (scheme, hashed) = if algorithm.oid == SHA_256_WITH_RSA_ENCRYPTION {
+        (Pkcs1v15Sign::new::<Sha256>(), Sha256::digest(tbs).to_vec())
+    } else if algorithm.oid == SHA_384_WITH_RSA_ENCRYPTION {
+        (Pkcs1v15Sign::new::<Sha384>(), Sha384::digest(tbs).to_vec())
+    } else if algorithm.oid == SHA_512_WITH_RSA_ENCRYPTION {
+        (Pkcs1v15Sign::new::<Sha512>(), Sha512::digest(tbs).to_vec())
+    } else if algorithm.oid == SHA_1_WITH_RSA_ENCRYPTION {
+        (Pkcs1v15Sign::new::<Sha1>(), Sha1::digest(tbs).to_vec())
+    } else {
+        return Err(format!("Unsupported signature algorithm {}", algorithm.oid));
+    };
+    key.verify(scheme, &hashed, signature).map_err(|_| "Signature does not match".to_string())
+}
+
+fn issued_by(certificate: &Certificate, issuer: &Certificate) -> bool {
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return false;
+    }
+    let Ok(tbs) = certificate.tbs_certificate.to_der() else { return false };
+    verify_signature(&tbs, &certificate.signature_algorithm, certificate.signature.raw_bytes(), issuer).is_ok()
+}
+
+fn check_period(certificate: &Certificate, now: SystemTime) -> Option<(Verdict, String)> {
+    let validity = &certificate.tbs_certificate.validity;
+    let subject = &certificate.tbs_certificate.subject;
+    if now < validity.not_before.to_system_time() {
+        return Some((Verdict::Invalid, format!("{} is valid only from {}", subject, validity.not_before)));
+    }
+    if now > validity.not_after.to_system_time() {
+        return Some((Verdict::Expired, format!("{} expired on {}", subject, validity.not_after)));
+    }
+    None
+}
+
+fn key_usage(certificate: &Certificate) -> Option<KeyUsage> {
+    certificate.tbs_certificate.get::<KeyUsage>().ok().flatten().map(|(_, usage)| usage)
+}
+
+fn check_leaf_usage(certificate: &Certificate, purpose: KeyPurpose) -> Option<String> {
+    let usage = key_usage(certificate)?;
+    match purpose {
+        KeyPurpose::Signing if !usage.non_repudiation() => Some("Key usage does not allow qualified signatures".to_string()),
+        KeyPurpose::Authentication if !usage.digital_signature() => Some("Key usage does not allow authentication".to_string()),
+        _ => None,
+    }
+}
+
+fn check_ca_usage(certificate: &Certificate) -> Option<String> {
+    let is_ca = certificate.tbs_certificate.get::<BasicConstraints>().ok().flatten().map(|(_, constraints)| constraints.ca);
+    if is_ca != Some(true) {
+        return Some(format!("{} is not a CA certificate", certificate.tbs_certificate.subject));
+    }
+    match key_usage(certificate) {
+        Some(usage) if !usage.key_cert_sign() => Some(format!("{} may not sign certificates", certificate.tbs_certificate.subject)),
+        _ => None,
+    }
+}
+
+/// Trusted CA certificates and CRLs. Every certificate in the store is trusted,
+/// chains have to end in a self-signed one.
+pub struct TrustStore {
+    pub certificates: Vec<Certificate>,
+    pub crls: Vec<CertificateList>,
+}
+
+impl TrustStore {
+    pub fn new(bundled: bool) -> Result<TrustStore, String> {
+        let certificates = if bundled { load_certificates(BUNDLED_CA.as_bytes())? } else { vec![] };
+        Ok(TrustStore { certificates, crls: vec![] })
+    }
+
+    /// Adds every certificate file in the directory, PEM or DER.
+    pub fn add_ca_dir(&mut self, dir: &str) -> Result<(), String> {
+        let entries = fs::read_dir(dir).map_err(|err| format!("{}: {}", dir, err))?;
+        for entry in entries {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let bytes = fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let certificates = load_certificates(&bytes).map_err(|err| format!("{}: {}", path.display(), err))?;
+            self.certificates.extend(certificates);
+        }
+        Ok(())
+    }
+
+    pub fn add_crl_file(&mut self, path: &str) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        self.crls.push(load_crl(&bytes).map_err(|err| format!("{}: {}", path, err))?);
+        Ok(())
+    }
+
+    /// Revocation status of `certificate` from CRLs signed by its issuer.
+    fn check_revocation(&self, certificate: &Certificate, issuer: &Certificate, now: SystemTime) -> Result<Option<String>, String> {
+        for crl in &self.crls {
+            if crl.tbs_cert_list.issuer != certificate.tbs_certificate.issuer {
+                continue;
+            }
+            let tbs = crl.tbs_cert_list.to_der().map_err(|err| err.to_string())?;
+            if verify_signature(&tbs, &crl.signature_algorithm, crl.signature.raw_bytes(), issuer).is_err() {
+                return Err(format!("CRL of {} has an invalid signature", crl.tbs_cert_list.issuer));
+            }
+            let revoked = crl.tbs_cert_list.revoked_certificates.iter().flatten()
+                .find(|revoked| revoked.serial_number == certificate.tbs_certificate.serial_number);
+            if let Some(revoked) = revoked {
+                return Ok(Some(format!("{} was revoked on {}", certificate.tbs_certificate.subject, revoked.revocation_date)));
+            }
+            if let Some(next_update) = crl.tbs_cert_list.next_update {
+                if now > next_update.to_system_time() {
+                    eprintln!("Warning: CRL of {} is outdated since {}", crl.tbs_cert_list.issuer, next_update);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Builds the chain up to a trusted self-signed CA and checks every certificate in it. All problems
+    /// are looked for, the worst one is reported, so an expired leaf from an unknown CA is untrusted.
+    pub fn validate(&self, certificate: &Certificate, purpose: KeyPurpose, now: SystemTime) -> Validation {
+        let mut chain = vec![certificate.clone()];
+        let mut problems = vec![];
+
+        problems.extend(check_period(certificate, now));
+        problems.extend(check_leaf_usage(certificate, purpose).map(|reason| (Verdict::Invalid, reason)));
+
+        loop {
+            let current = chain.last().unwrap();
+            let self_signed = current.tbs_certificate.subject == current.tbs_certificate.issuer;
+            if self_signed && chain.len() > 1 {
+                break;
+            }
+            let issuer = match self.certificates.iter().find(|ca| issued_by(current, ca)) {
+                Some(issuer) => issuer.clone(),
+                None => {
+                    problems.push((Verdict::Untrusted, format!("Issuer {} is not a trusted CA", current.tbs_certificate.issuer)));
+                    break;
+                }
+            };
+
+            problems.extend(check_period(&issuer, now));
+            problems.extend(check_ca_usage(&issuer).map(|reason| (Verdict::Invalid, reason)));
+            match self.check_revocation(current, &issuer, now) {
+                Ok(Some(reason)) => problems.push((Verdict::Revoked, reason)),
+                Ok(None) => {}
+                Err(reason) => problems.push((Verdict::Untrusted, reason)),
+            }
+
+            chain.push(issuer);
+            if chain.len() > MAX_CHAIN_LENGTH {
+                problems.push((Verdict::Untrusted, "Certificate chain is too long".to_string()));
+                break;
+            }
+        }
+
+        // The first of the worst problems, which is the one nearest the leaf
+        let worst = problems.into_iter().reduce(|worst, problem| if problem.0 > worst.0 { problem } else { worst });
+        match worst {
+            Some((verdict, reason)) => Validation::fail(verdict, reason, chain),
+            None => Validation { verdict: Verdict::Valid, reason: None, chain },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::testing::{self, DAY};
+
+    struct Pki {
+        root: Certificate,
+        store: TrustStore,
+    }
+
+    fn pki() -> Pki {
+        let root = testing::root("CN=Test Root,O=Test", testing::key(0));
+        let store = TrustStore { certificates: vec![root.clone()], crls: vec![] };
+        Pki { root, store }
+    }
+
+    fn leaf(pki: &Pki, not_before: SystemTime, not_after: SystemTime) -> Certificate {
+        testing::leaf(&pki.root, testing::key(0), 2, not_before, not_after)
+    }
+
+    fn current_leaf(pki: &Pki) -> Certificate {
+        let now = SystemTime::now();
+        leaf(pki, now - DAY, now + 365 * DAY)
+    }
+
+    fn validate(store: &TrustStore, certificate: &Certificate) -> Validation {
+        store.validate(certificate, KeyPurpose::Signing, SystemTime::now())
+    }
+
+    #[test]
+    fn valid_chain_ends_in_the_root() {
+        let pki = pki();
+        let certificate = current_leaf(&pki);
+        let validation = validate(&pki.store, &certificate);
+        assert_eq!(validation.verdict, Verdict::Valid);
+        assert_eq!(validation.reason, None);
+        assert_eq!(validation.chain, [certificate, pki.root.clone()]);
+    }
+
+    #[test]
+    fn chain_through_an_intermediate_ca() {
+        let mut pki = pki();
+        let now = SystemTime::now();
+        let profile = x509_cert::builder::Profile::SubCA { issuer: pki.root.tbs_certificate.subject.clone(), path_len_constraint: None };
+        let intermediate = testing::certificate(profile, 5, "CN=Test Intermediate,O=Test", testing::key(2), testing::key(0), now - DAY, now + 365 * DAY);
+        pki.store.certificates.push(intermediate.clone());
+        let certificate = testing::leaf(&intermediate, testing::key(2), 2, now - DAY, now + 365 * DAY);
+
+        let validation = validate(&pki.store, &certificate);
+        assert_eq!(validation.verdict, Verdict::Valid);
+        assert_eq!(validation.chain, [certificate.clone(), intermediate.clone(), pki.root.clone()]);
+
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(0), &[&intermediate]));
+        assert_eq!(validate(&pki.store, &certificate).verdict, Verdict::Revoked);
+    }
+
+    #[test]
+    fn expired() {
+        let pki = pki();
+        let now = SystemTime::now();
+        let validation = validate(&pki.store, &leaf(&pki, now - 365 * DAY, now - DAY));
+        assert_eq!(validation.verdict, Verdict::Expired);
+    }
+
+    #[test]
+    fn revoked() {
+        let mut pki = pki();
+        let certificate = current_leaf(&pki);
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(0), &[&certificate]));
+        let validation = validate(&pki.store, &certificate);
+        assert_eq!(validation.verdict, Verdict::Revoked);
+    }
+
+    #[test]
+    fn crl_without_the_certificate() {
+        let mut pki = pki();
+        let certificate = current_leaf(&pki);
+        let other = testing::leaf(&pki.root, testing::key(0), 3, SystemTime::now() - DAY, SystemTime::now() + DAY);
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(0), &[&other]));
+        assert_eq!(validate(&pki.store, &certificate).verdict, Verdict::Valid);
+    }
+
+    #[test]
+    fn untrusted() {
+        let pki = pki();
+        let validation = validate(&TrustStore::new(false).unwrap(), &current_leaf(&pki));
+        assert_eq!(validation.verdict, Verdict::Untrusted);
+        assert_eq!(validation.chain.len(), 1);
+
+        // Same name, different key
+        let impostor = testing::root("CN=Test Root,O=Test", testing::key(2));
+        let store = TrustStore { certificates: vec![impostor], crls: vec![] };
+        assert_eq!(validate(&store, &current_leaf(&pki)).verdict, Verdict::Untrusted);
+    }
+
+    #[test]
+    fn crl_with_a_bad_signature_is_untrusted() {
+        let mut pki = pki();
+        let certificate = current_leaf(&pki);
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(2), &[]));
+        assert_eq!(validate(&pki.store, &certificate).verdict, Verdict::Untrusted);
+    }
+
+    #[test]
+    fn invalid() {
+        let pki = pki();
+        let now = SystemTime::now();
+        let not_yet_valid = leaf(&pki, now + DAY, now + 365 * DAY);
+        assert_eq!(validate(&pki.store, &not_yet_valid).verdict, Verdict::Invalid);
+
+        // A CA certificate may not make qualified signatures
+        assert_eq!(validate(&pki.store, &pki.root).verdict, Verdict::Invalid);
+    }
+
+    #[test]
+    fn worst_verdict_wins() {
+        let mut pki = pki();
+        let now = SystemTime::now();
+        let expired = leaf(&pki, now - 365 * DAY, now - DAY);
+        let validation = validate(&TrustStore::new(false).unwrap(), &expired);
+        assert_eq!(validation.verdict, Verdict::Untrusted);
+
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(0), &[&expired]));
+        assert_eq!(validate(&pki.store, &expired).verdict, Verdict::Revoked);
+
+        // Later in time the root has expired too, the leaf being revoked is still worse
+        let later = now + 20 * 365 * DAY;
+        assert_eq!(pki.store.validate(&expired, KeyPurpose::Signing, later).verdict, Verdict::Revoked);
+
+        let not_yet_valid = leaf(&pki, now + DAY, now + 365 * DAY);
+        pki.store.crls.push(testing::crl(&pki.root, testing::key(0), &[&not_yet_valid]));
+        assert_eq!(validate(&pki.store, &not_yet_valid).verdict, Verdict::Invalid);
+    }
+
+    #[test]
+    fn bundled_file_parses() {
+        assert!(TrustStore::new(true).is_ok());
+    }
+}
@@ -0,0 +1,237 @@
+use std::time::SystemTime;
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::{CmsVersion, ContentInfo};
+use cms::signed_data::{
+    CertificateSet, EncapsulatedContentInfo, SignedAttributes, SignedData, SignerIdentifier, SignerInfo, SignerInfos,
+};
+use const_oid::db::rfc5911::{ID_CONTENT_TYPE, ID_DATA, ID_MESSAGE_DIGEST, ID_SIGNED_DATA, ID_SIGNING_TIME};
+use const_oid::db::rfc5912::{ID_SHA_256, RSA_ENCRYPTION, SHA_256_WITH_RSA_ENCRYPTION};
+use der::asn1::{OctetString, SetOfVec};
+use der::{Any, Decode, Encode, Sequence};
+use sha2::{Digest, Sha256};
+use x509_cert::attr::Attribute;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use x509_cert::time::Time;
+use x509_cert::Certificate;
+
+use super::verify_rsa_sha256;
+
+fn algorithm(oid: der::oid::ObjectIdentifier) -> AlgorithmIdentifierOwned {
+    AlgorithmIdentifierOwned { oid, parameters: None }
+}
+
+/// id-aa-signingCertificateV2 from RFC 5035
+const ID_AA_SIGNING_CERTIFICATE_V2: der::oid::ObjectIdentifier = der::oid::ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.2.47");
+
+/// ESSCertIDv2 with the default SHA-256 hash algorithm and no issuer serial
+#[derive(Sequence)]
+struct EssCertIdV2 {
+    cert_hash: OctetString,
+}
+
+#[derive(Sequence)]
+struct SigningCertificateV2 {
+    certs: Vec<EssCertIdV2>,
+}
+
+pub fn attribute(oid: der::oid::ObjectIdentifier, value: impl Encode) -> Result<Attribute, String> {
+    let value = Any::from_der(&value.to_der().map_err(|err| err.to_string())?).map_err(|err| err.to_string())?;
+    let values = SetOfVec::try_from(vec![value]).map_err(|err| err.to_string())?;
+    Ok(Attribute { oid, values })
+}
+
+pub fn signing_time_attribute() -> Result<Attribute, String> {
+    let signing_time = Time::try_from(SystemTime::now()).map_err(|err| err.to_string())?;
+    attribute(ID_SIGNING_TIME, signing_time)
+}
+
+/// Binds the signer certificate to the signature, required by CAdES and PAdES.
+pub fn signing_certificate_attribute(certificate: &Certificate) -> Result<Attribute, String> {
+    let certificate_der = certificate.to_der().map_err(|err| err.to_string())?;
+    let cert_hash = OctetString::new(Sha256::digest(certificate_der).to_vec()).map_err(|err| err.to_string())?;
+    attribute(ID_AA_SIGNING_CERTIFICATE_V2, SigningCertificateV2 { certs: vec![EssCertIdV2 { cert_hash }] })
+}
+
+/// Builds a detached CMS SignedData over content with the given SHA-256 digest.
+/// Content type and message digest are always signed, `extra_attributes` are added to them.
+/// `sign` gets the SHA-256 of the DER encoded signed attributes and returns the raw RSA signature.
+pub fn build_detached<F>(certificate: &Certificate, content_digest: &[u8; 32], extra_attributes: Vec<Attribute>, sign: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&[u8; 32]) -> Result<Vec<u8>, String>,
+{
+    let message_digest = OctetString::new(content_digest.to_vec()).map_err(|err| err.to_string())?;
+
+    let mut attributes = vec![
+        attribute(ID_CONTENT_TYPE, ID_DATA)?,
+        attribute(ID_MESSAGE_DIGEST, message_digest)?,
+    ];
+    attributes.extend(extra_attributes);
+    let signed_attrs: SignedAttributes = SetOfVec::try_from(attributes).map_err(|err| err.to_string())?;
+
+    let signed_attrs_der = signed_attrs.to_der().map_err(|err| err.to_string())?;
+    let signature = sign(&Sha256::digest(signed_attrs_der).into())?;
+
+    let sid = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+        issuer: certificate.tbs_certificate.issuer.clone(),
+        serial_number: certificate.tbs_certificate.serial_number.clone(),
+    });
+    let signer_info = SignerInfo {
+        version: CmsVersion::V1,
+        sid,
+        digest_alg: algorithm(ID_SHA_256),
+        signed_attrs: Some(signed_attrs),
+        signature_algorithm: algorithm(RSA_ENCRYPTION),
+        signature: OctetString::new(signature).map_err(|err| err.to_string())?,
+        unsigned_attrs: None,
+    };
+
+    let certificates = SetOfVec::try_from(vec![CertificateChoices::Certificate(certificate.clone())]).map_err(|err| err.to_string())?;
+    let signed_data = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: SetOfVec::try_from(vec![algorithm(ID_SHA_256)]).map_err(|err| err.to_string())?,
+        encap_content_info: EncapsulatedContentInfo { econtent_type: ID_DATA, econtent: None },
+        certificates: Some(CertificateSet(certificates)),
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::try_from(vec![signer_info]).map_err(|err| err.to_string())?),
+    };
+
+    let content_info = ContentInfo {
+        content_type: ID_SIGNED_DATA,
+        content: Any::encode_from(&signed_data).map_err(|err| err.to_string())?,
+    };
+    content_info.to_der().map_err(|err| err.to_string())
+}
+
+fn find_signer<'a>(signed_data: &'a SignedData, signer_info: &SignerInfo) -> Result<&'a Certificate, String> {
+    let SignerIdentifier::IssuerAndSerialNumber(sid) = &signer_info.sid else {
+        return Err("Signer identified by key identifier is not supported".to_string());
+    };
+    let certificates = signed_data.certificates.as_ref().ok_or("Signature does not contain the signer certificate")?;
+    certificates
+        .0
+        .iter()
+        .find_map(|choice| match choice {
+            CertificateChoices::Certificate(cert)
+                if cert.tbs_certificate.issuer == sid.issuer && cert.tbs_certificate.serial_number == sid.serial_number =>
+            {
+                Some(cert)
+            }
+            _ => None,
+        })
+        .ok_or_else(|| "Signature does not contain the signer certificate".to_string())
+}
+
+/// Verifies a detached CMS signature offline and returns the signer certificate.
+/// Only checks the signature itself, trust in the certificate is not evaluated.
+pub fn verify_detached(cms_der: &[u8], content_digest: &[u8; 32]) -> Result<Certificate, String> {
+    let content_info = ContentInfo::from_der(cms_der).map_err(|err| format!("Invalid CMS structure: {}", err))?;
+    if content_info.content_type != ID_SIGNED_DATA {
+        return Err("CMS structure is not SignedData".to_string());
+    }
+    let signed_data: SignedData = content_info.content.decode_as().map_err(|err| format!("Invalid SignedData: {}", err))?;
+    let signer_info = signed_data.signer_infos.0.iter().next().ok_or("Signature has no signers")?;
+
+    if signer_info.digest_alg.oid != ID_SHA_256 {
+        return Err(format!("Unsupported digest algorithm {}", signer_info.digest_alg.oid));
+    }
+    if signer_info.signature_algorithm.oid != RSA_ENCRYPTION && signer_info.signature_algorithm.oid != SHA_256_WITH_RSA_ENCRYPTION {
+        return Err(format!("Unsupported signature algorithm {}", signer_info.signature_algorithm.oid));
+    }
+    let certificate = find_signer(&signed_data, signer_info)?;
+
+    let signed_digest: [u8; 32] = match &signer_info.signed_attrs {
+        Some(signed_attrs) => {
+            let content_type = signed_attrs
+                .iter()
+                .find(|attr| attr.oid == ID_CONTENT_TYPE)
+                .and_then(|attr| attr.values.iter().next())
+                .ok_or("Signature has no content type attribute")?;
+            let content_type: der::oid::ObjectIdentifier = content_type.decode_as().map_err(|err| err.to_string())?;
+            if content_type != ID_DATA {
+                return Err(format!("Unsupported signed content type {}", content_type));
+            }
+            let message_digest = signed_attrs
+                .iter()
+                .find(|attr| attr.oid == ID_MESSAGE_DIGEST)
+                .and_then(|attr| attr.values.iter().next())
+                .ok_or("Signature has no message digest attribute")?;
+            let message_digest: OctetString = message_digest.decode_as().map_err(|err| err.to_string())?;
+            if message_digest.as_bytes() != content_digest {
+                return Err("Content does not match the signature".to_string());
+            }
+            Sha256::digest(signed_attrs.to_der().map_err(|err| err.to_string())?).into()
+        }
+        None => *content_digest,
+    };
+
+    verify_rsa_sha256(certificate, &signed_digest, signer_info.signature.as_bytes())?;
+    Ok(certificate.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::testing::{self, DAY};
+
+    fn signer() -> Certificate {
+        let ca = testing::root("CN=Test CA,O=Test", testing::key(0));
+        let now = SystemTime::now();
+        testing::leaf(&ca, testing::key(0), 2, now - DAY, now + 365 * DAY)
+    }
+
+    fn digest(content: &[u8]) -> [u8; 32] {
+        Sha256::digest(content).into()
+    }
+
+    #[test]
+    fn round_trip() {
+        let certificate = signer();
+        let digest = digest(b"content");
+        let attributes = vec![signing_time_attribute().unwrap(), signing_certificate_attribute(&certificate).unwrap()];
+        let cms = build_detached(&certificate, &digest, attributes, |hash| testing::sign_digest(testing::key(1), hash)).unwrap();
+        assert_eq!(verify_detached(&cms, &digest).unwrap(), certificate);
+    }
+
+    #[test]
+    fn tampered_content_is_rejected() {
+        let certificate = signer();
+        let cms = build_detached(&certificate, &digest(b"content"), vec![], |hash| testing::sign_digest(testing::key(1), hash)).unwrap();
+        assert_eq!(verify_detached(&cms, &digest(b"c0ntent")).unwrap_err(), "Content does not match the signature");
+    }
+
+    #[test]
+    fn wrong_certificate_is_rejected() {
+        let certificate = signer();
+        let digest = digest(b"content");
+        let cms = build_detached(&certificate, &digest, vec![], |hash| testing::sign_digest(testing::key(2), hash)).unwrap();
+        assert_eq!(verify_detached(&cms, &digest).unwrap_err(), "Signature does not match");
+    }
+
+    #[test]
+    fn content_type_must_be_data() {
+        let certificate = signer();
+        let digest = digest(b"content");
+        let cms = build_detached(&certificate, &digest, vec![], |hash| testing::sign_digest(testing::key(1), hash)).unwrap();
+
+        // Re-sign the same structure with the content type changed to SignedData
+        let mut content_info = ContentInfo::from_der(&cms).unwrap();
+        let mut signed_data: SignedData = content_info.content.decode_as().unwrap();
+        let mut signer_info = signed_data.signer_infos.0.iter().next().unwrap().clone();
+        let attributes = signer_info
+            .signed_attrs
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .map(|attr| if attr.oid == ID_CONTENT_TYPE { attribute(ID_CONTENT_TYPE, ID_SIGNED_DATA).unwrap() } else { attr })
+            .collect::<Vec<_>>();
+        let signed_attrs: SignedAttributes = SetOfVec::try_from(attributes).unwrap();
+        let signature = testing::sign_digest(testing::key(1), &Sha256::digest(signed_attrs.to_der().unwrap()).into()).unwrap();
+        signer_info.signed_attrs = Some(signed_attrs);
+        signer_info.signature = OctetString::new(signature).unwrap();
+        signed_data.signer_infos = SignerInfos(SetOfVec::try_from(vec![signer_info]).unwrap());
+        content_info.content = Any::encode_from(&signed_data).unwrap();
+
+        let error = verify_detached(&content_info.to_der().unwrap(), &digest).unwrap_err();
+        assert!(error.starts_with("Unsupported signed content type"), "{}", error);
+    }
+}
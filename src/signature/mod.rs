@@ -0,0 +1,28 @@
+pub mod cms;
+pub mod c14n;
+pub mod xades;
+pub mod pades;
+pub mod chain;
+#[cfg(test)]
+mod testing;
+
+use der::{Decode, Encode};
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Sign, RsaPublicKey};
+use sha2::Sha256;
+use x509_cert::Certificate;
+
+pub fn parse_certificate(der: &[u8]) -> Result<Certificate, String> {
+    Certificate::from_der(der).map_err(|err| format!("Invalid certificate: {}", err))
+}
+
+pub fn rsa_public_key(certificate: &Certificate) -> Result<RsaPublicKey, String> {
+    let spki = certificate.tbs_certificate.subject_public_key_info.to_der().map_err(|err| err.to_string())?;
+    RsaPublicKey::from_public_key_der(&spki).map_err(|err| format!("Only RSA keys are supported: {}", err))
+}
+
+/// Checks an RSA PKCS#1 v1.5 signature over a SHA-256 digest against the certificate's key.
+pub fn verify_rsa_sha256(certificate: &Certificate, digest: &[u8; 32], signature: &[u8]) -> Result<(), String> {
+    rsa_public_key(certificate)?
+        .verify(Pkcs1v15Sign::new::<Sha256>(), digest, signature)
+        .map_err(|_| "Signature does not match".to_string())
+}
@@ -0,0 +1,208 @@
+use std::ops::Range;
+use std::time::SystemTime;
+use der::DateTime;
+use printpdf::lopdf::{self, dictionary, Object, StringFormat};
+use sha2::{Digest, Sha256};
+use x509_cert::Certificate;
+
+use super::cms;
+
+/// Space reserved for the CMS structure, certificate included
+const SIGNATURE_SIZE: usize = 8192;
+const BYTE_RANGE_PLACEHOLDER: i64 = 9_999_999_999;
+
+fn pdf_date(time: SystemTime) -> Result<String, String> {
+    let time = DateTime::from_system_time(time).map_err(|err| err.to_string())?;
+    Ok(format!("D:{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        time.year(), time.month(), time.day(), time.hour(), time.minutes(), time.seconds()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Offset of an object in a PDF with a single classic cross-reference table, as lopdf writes it.
+fn object_offset(pdf: &[u8], id: u32) -> Option<usize> {
+    let startxref = pdf.len() - pdf.windows(9).rev().position(|window| window == b"startxref")? - 9;
+    let xref_start: usize = std::str::from_utf8(&pdf[startxref + 9..]).ok()?.split_whitespace().next()?.parse().ok()?;
+    let table = pdf.get(xref_start..)?.strip_prefix(b"xref")?;
+    let table = &table[table.iter().position(|b| !b.is_ascii_whitespace())?..];
+    let header_end = table.iter().position(|&b| b == b'\n')?;
+    let mut header = std::str::from_utf8(&table[..header_end]).ok()?.split_whitespace();
+    let (first, count): (u32, u32) = (header.next()?.parse().ok()?, header.next()?.parse().ok()?);
+    if id < first || id >= first + count {
+        return None;
+    }
+    // Every entry is exactly 20 bytes: 10 digit offset, 5 digit generation, type and end of line
+    let entry = table.get(header_end + 1 + 20 * (id - first) as usize..)?.get(..18)?;
+    if entry[17] != b'n' {
+        return None;
+    }
+    std::str::from_utf8(&entry[..10]).ok()?.parse().ok()
+}
+
+/// Byte ranges of the `[...]` ByteRange array and the `<...>` Contents string of the written
+/// signature dictionary. Its keys come before the caller supplied strings, so the first match
+/// inside the object is the key itself.
+fn signature_value_ranges(pdf: &[u8], signature_id: lopdf::ObjectId) -> Result<(Range<usize>, Range<usize>), String> {
+    let start = object_offset(pdf, signature_id.0).ok_or("Signature dictionary not found")?;
+    let object = pdf.get(start..).ok_or("Signature dictionary not found")?;
+    let object = &object[..find(object, b"endobj").ok_or("Signature dictionary not found")?];
+
+    let value = |key: &[u8], open: u8, close: u8| -> Result<Range<usize>, String> {
+        let missing = || format!("Signature dictionary has no {}", String::from_utf8_lossy(&key[1..]));
+        let key_end = find(object, key).ok_or_else(missing)? + key.len();
+        let value_start = key_end + object[key_end..].iter().position(|b| !b.is_ascii_whitespace()).ok_or_else(missing)?;
+        if object[value_start] != open {
+            return Err(missing());
+        }
+        let value_end = value_start + object[value_start..].iter().position(|&b| b == close).ok_or_else(missing)? + 1;
+        Ok(start + value_start..start + value_end)
+    };
+    Ok((value(b"/ByteRange", b'[', b']')?, value(b"/Contents", b'<', b'>')?))
+}
+
+/// Adds an invisible signature field to the first page with a PAdES (ETSI.CAdES.detached) signature.
+/// `sign` gets the SHA-256 of the CMS signed attributes and returns the raw RSA signature.
+pub fn sign<F>(pdf: &[u8], certificate: &Certificate, reason: &str, sign: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&[u8; 32]) -> Result<Vec<u8>, String>,
+{
+    let mut doc = lopdf::Document::load_mem(pdf).map_err(|err| err.to_string())?;
+
+    let signature_id = doc.add_object(dictionary! {
+        "Type" => "Sig",
+        "Filter" => "Adobe.PPKLite",
+        "SubFilter" => "ETSI.CAdES.detached",
+        "ByteRange" => vec![0.into(), BYTE_RANGE_PLACEHOLDER.into(), BYTE_RANGE_PLACEHOLDER.into(), BYTE_RANGE_PLACEHOLDER.into()],
+        "Contents" => Object::String(vec![0; SIGNATURE_SIZE], StringFormat::Hexadecimal),
+        "M" => Object::string_literal(pdf_date(SystemTime::now())?),
+        "Reason" => Object::string_literal(reason),
+    });
+
+    let page_id = *doc.get_pages().values().next().ok_or("PDF has no pages")?;
+    let field_id = doc.add_object(dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Widget",
+        "FT" => "Sig",
+        "T" => Object::string_literal("Signature1"),
+        "V" => signature_id,
+        "F" => 132,
+        "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+        "P" => page_id,
+    });
+
+    let page = doc.get_object_mut(page_id).and_then(Object::as_dict_mut).map_err(|err| err.to_string())?;
+    match page.get_mut(b"Annots").and_then(Object::as_array_mut) {
+        Ok(annots) => annots.push(field_id.into()),
+        Err(_) => page.set("Annots", vec![field_id.into()]),
+    }
+
+    let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference).map_err(|err| err.to_string())?;
+    let catalog = doc.get_object_mut(catalog_id).and_then(Object::as_dict_mut).map_err(|err| err.to_string())?;
+    catalog.set("AcroForm", dictionary! {
+        "Fields" => vec![field_id.into()],
+        "SigFlags" => 3,
+    });
+
+    let mut out = vec![];
+    doc.save_to(&mut out).map_err(|err| err.to_string())?;
+
+    // Everything except the hex string in /Contents is signed. Both values are located inside
+    // the signature object and patched in place, so that no offset in the file moves.
+    let (byte_range, contents) = signature_value_ranges(&out, signature_id)?;
+    if contents.len() != SIGNATURE_SIZE * 2 + 2 {
+        return Err("Signature placeholder not found".to_string());
+    }
+    let (contents_start, contents_end) = (contents.start, contents.end);
+    let value = format!("[0 {} {} {}", contents_start, contents_end, out.len() - contents_end);
+    let value = format!("{:<width$}]", value, width = byte_range.len() - 1);
+    if value.len() != byte_range.len() {
+        return Err("Byte range does not fit in the space reserved in the PDF".to_string());
+    }
+    out[byte_range].copy_from_slice(value.as_bytes());
+
+    let mut hasher = Sha256::new();
+    hasher.update(&out[..contents_start]);
+    hasher.update(&out[contents_end..]);
+    let digest: [u8; 32] = hasher.finalize().into();
+
+    let attributes = vec![cms::signing_certificate_attribute(certificate)?];
+    let signature = cms::build_detached(certificate, &digest, attributes, sign)?;
+    if signature.len() > SIGNATURE_SIZE {
+        return Err("Signature does not fit in the space reserved in the PDF".to_string());
+    }
+    let hex: String = signature.iter().map(|b| format!("{:02X}", b)).collect();
+    out[contents_start + 1..contents_start + 1 + hex.len()].copy_from_slice(hex.as_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::testing::{self, DAY};
+    use der::{Any, Decode, Encode, SliceReader};
+
+    fn pdf() -> Vec<u8> {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 12 Tf 72 720 Td (Test) Tj ET".to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let mut out = vec![];
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn byte_range_covers_everything_but_the_signature() {
+        let ca = testing::root("CN=Test CA,O=Test", testing::key(0));
+        let now = SystemTime::now();
+        let certificate = testing::leaf(&ca, testing::key(0), 2, now - DAY, now + 365 * DAY);
+        // A reason that looks like the placeholders must not confuse the patching
+        let reason = "/Contents <00> /ByteRange [0 9999999999 9999999999 9999999999]";
+
+        let signed = sign(&pdf(), &certificate, reason, |digest| testing::sign_digest(testing::key(1), digest)).unwrap();
+
+        let doc = lopdf::Document::load_mem(&signed).unwrap();
+        let signature = doc.objects.values()
+            .filter_map(|object| object.as_dict().ok())
+            .find(|dict| dict.get(b"Type").and_then(Object::as_name).ok() == Some(b"Sig".as_ref()))
+            .unwrap();
+        assert_eq!(signature.get(b"Reason").and_then(Object::as_str).unwrap(), reason.as_bytes());
+
+        let byte_range: Vec<usize> = signature.get(b"ByteRange").and_then(Object::as_array).unwrap()
+            .iter()
+            .map(|value| value.as_i64().unwrap() as usize)
+            .collect();
+        let (gap_start, gap_end) = (byte_range[1], byte_range[2]);
+        assert_eq!(byte_range[0], 0);
+        assert_eq!(gap_end + byte_range[3], signed.len());
+        assert_eq!(signed[gap_start], b'<');
+        assert_eq!(signed[gap_end - 1], b'>');
+
+        let contents = signature.get(b"Contents").and_then(Object::as_str).unwrap();
+        assert_eq!(contents.len(), SIGNATURE_SIZE);
+        let hex: String = contents.iter().map(|b| format!("{:02X}", b)).collect();
+        assert_eq!(&signed[gap_start + 1..gap_end - 1], hex.as_bytes());
+
+        // The CMS is padded with zeros up to the reserved size
+        let cms = Any::decode(&mut SliceReader::new(contents).unwrap()).unwrap().to_der().unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(&signed[..gap_start]);
+        hasher.update(&signed[gap_end..]);
+        let digest: [u8; 32] = hasher.finalize().into();
+        assert_eq!(cms::verify_detached(&cms, &digest).unwrap(), certificate);
+    }
+}
@@ -0,0 +1,98 @@
+//! Software keys, certificates and CRLs for the signature tests
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use der::asn1::BitString;
+use der::Encode;
+use rsa::pkcs1v15::SigningKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use sha2::Sha256;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::crl::{CertificateList, RevokedCert, TbsCertList};
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{DynSignatureAlgorithmIdentifier, SubjectPublicKeyInfoOwned};
+use x509_cert::time::{Time, Validity};
+use x509_cert::{Certificate, Version};
+
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Generating RSA keys is slow, the tests share a handful of them.
+pub fn key(index: usize) -> &'static RsaPrivateKey {
+    static KEYS: OnceLock<Vec<RsaPrivateKey>> = OnceLock::new();
+    &KEYS.get_or_init(|| {
+        (0..3).map(|_| RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap()).collect()
+    })[index]
+}
+
+/// Raw RSA PKCS#1 v1.5 signature over a SHA-256 digest, what the card returns.
+pub fn sign_digest(key: &RsaPrivateKey, digest: &[u8; 32]) -> Result<Vec<u8>, String> {
+    key.sign(Pkcs1v15Sign::new::<Sha256>(), digest).map_err(|err| err.to_string())
+}
+
+fn time(time: SystemTime) -> Time {
+    Time::try_from(time).unwrap()
+}
+
+pub fn certificate(
+    profile: Profile,
+    serial: u32,
+    subject: &str,
+    key: &RsaPrivateKey,
+    issuer_key: &RsaPrivateKey,
+    not_before: SystemTime,
+    not_after: SystemTime,
+) -> Certificate {
+    let validity = Validity { not_before: time(not_before), not_after: time(not_after) };
+    let spki = SubjectPublicKeyInfoOwned::from_key(key.to_public_key()).unwrap();
+    let signer = SigningKey::<Sha256>::new(issuer_key.clone());
+    CertificateBuilder::new(profile, SerialNumber::from(serial), validity, Name::from_str(subject).unwrap(), spki, &signer)
+        .unwrap()
+        .build::<rsa::pkcs1v15::Signature>()
+        .unwrap()
+}
+
+/// Self-signed root valid for ten years around now.
+pub fn root(subject: &str, key: &RsaPrivateKey) -> Certificate {
+    let now = SystemTime::now();
+    certificate(Profile::Root, 1, subject, key, key, now - 365 * DAY, now + 10 * 365 * DAY)
+}
+
+/// Signing certificate issued by `issuer`.
+pub fn leaf(issuer: &Certificate, issuer_key: &RsaPrivateKey, serial: u32, not_before: SystemTime, not_after: SystemTime) -> Certificate {
+    let profile = Profile::Leaf {
+        issuer: issuer.tbs_certificate.subject.clone(),
+        enable_key_agreement: false,
+        enable_key_encipherment: false,
+    };
+    certificate(profile, serial, "CN=Test Signer,O=Test", key(1), issuer_key, not_before, not_after)
+}
+
+/// CRL from `issuer` listing `revoked`.
+pub fn crl(issuer: &Certificate, issuer_key: &RsaPrivateKey, revoked: &[&Certificate]) -> CertificateList {
+    let signer = SigningKey::<Sha256>::new(issuer_key.clone());
+    let signature_algorithm = signer.signature_algorithm_identifier().unwrap();
+    let now = SystemTime::now();
+    let revoked_certificates = revoked
+        .iter()
+        .map(|cert| RevokedCert {
+            serial_number: cert.tbs_certificate.serial_number.clone(),
+            revocation_date: time(now - DAY),
+            crl_entry_extensions: None,
+        })
+        .collect();
+    let tbs_cert_list = TbsCertList {
+        version: Version::V2,
+        signature: signature_algorithm.clone(),
+        issuer: issuer.tbs_certificate.subject.clone(),
+        this_update: time(now - DAY),
+        next_update: Some(time(now + 30 * DAY)),
+        revoked_certificates: Some(revoked_certificates),
+        crl_extensions: None,
+    };
+    let signature = signer.sign(&tbs_cert_list.to_der().unwrap()).to_vec();
+    CertificateList { tbs_cert_list, signature_algorithm, signature: BitString::from_bytes(&signature).unwrap() }
+}
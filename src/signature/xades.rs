@@ -0,0 +1,206 @@
+use std::time::SystemTime;
+use der::{DateTime, Encode};
+use rsa::BigUint;
+use sha2::{Digest, Sha256};
+use x509_cert::Certificate;
+
+use super::c14n;
+
+pub const XMLDSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+pub const XADES_NS: &str = "http://uri.etsi.org/01903/v1.3.2#";
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const SIGNED_PROPERTIES_TYPE: &str = "http://uri.etsi.org/01903#SignedProperties";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\r', "&#xD;")
+}
+
+fn xsd_date_time(time: SystemTime) -> Result<String, String> {
+    let time = DateTime::from_system_time(time).map_err(|err| err.to_string())?;
+    Ok(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.year(), time.month(), time.day(), time.hour(), time.minutes(), time.seconds()))
+}
+
+/// The signed properties exactly as exclusive canonicalization renders them,
+/// so the same text can be digested and embedded.
+fn signed_properties(id: &str, certificate: &Certificate, signing_time: &str) -> Result<String, String> {
+    let certificate_der = certificate.to_der().map_err(|err| err.to_string())?;
+    let certificate_digest = base64::encode(Sha256::digest(&certificate_der));
+    let issuer = escape(&certificate.tbs_certificate.issuer.to_string());
+    let serial = BigUint::from_bytes_be(certificate.tbs_certificate.serial_number.as_bytes());
+
+    Ok(format!(concat!(
+        "<xades:SignedProperties xmlns:xades=\"{xades}\" Id=\"{id}-signed-properties\">",
+        "<xades:SignedSignatureProperties>",
+        "<xades:SigningTime>{time}</xades:SigningTime>",
+        "<xades:SigningCertificate><xades:Cert><xades:CertDigest>",
+        "<ds:DigestMethod xmlns:ds=\"{ds}\" Algorithm=\"{sha256}\"></ds:DigestMethod>",
+        "<ds:DigestValue xmlns:ds=\"{ds}\">{digest}</ds:DigestValue>",
+        "</xades:CertDigest><xades:IssuerSerial>",
+        "<ds:X509IssuerName xmlns:ds=\"{ds}\">{issuer}</ds:X509IssuerName>",
+        "<ds:X509SerialNumber xmlns:ds=\"{ds}\">{serial}</ds:X509SerialNumber>",
+        "</xades:IssuerSerial></xades:Cert></xades:SigningCertificate>",
+        "</xades:SignedSignatureProperties>",
+        "</xades:SignedProperties>"),
+        xades = XADES_NS, ds = XMLDSIG_NS, sha256 = SHA256, id = id, time = signing_time,
+        digest = certificate_digest, issuer = issuer, serial = serial))
+}
+
+fn signed_info(id: &str, document_digest: &str, properties_digest: &str) -> String {
+    format!(concat!(
+        "<ds:SignedInfo xmlns:ds=\"{ds}\">",
+        "<ds:CanonicalizationMethod Algorithm=\"{c14n}\"></ds:CanonicalizationMethod>",
+        "<ds:SignatureMethod Algorithm=\"{rsa_sha256}\"></ds:SignatureMethod>",
+        "<ds:Reference Id=\"{id}-document\" URI=\"\">",
+        "<ds:Transforms>",
+        "<ds:Transform Algorithm=\"{enveloped}\"></ds:Transform>",
+        "<ds:Transform Algorithm=\"{c14n}\"></ds:Transform>",
+        "</ds:Transforms>",
+        "<ds:DigestMethod Algorithm=\"{sha256}\"></ds:DigestMethod>",
+        "<ds:DigestValue>{document_digest}</ds:DigestValue>",
+        "</ds:Reference>",
+        "<ds:Reference Type=\"{properties_type}\" URI=\"#{id}-signed-properties\">",
+        "<ds:Transforms><ds:Transform Algorithm=\"{c14n}\"></ds:Transform></ds:Transforms>",
+        "<ds:DigestMethod Algorithm=\"{sha256}\"></ds:DigestMethod>",
+        "<ds:DigestValue>{properties_digest}</ds:DigestValue>",
+        "</ds:Reference>",
+        "</ds:SignedInfo>"),
+        ds = XMLDSIG_NS, c14n = EXC_C14N, rsa_sha256 = RSA_SHA256, enveloped = ENVELOPED_SIGNATURE,
+        sha256 = SHA256, properties_type = SIGNED_PROPERTIES_TYPE, id = id,
+        document_digest = document_digest, properties_digest = properties_digest)
+}
+
+/// Adds an enveloped XAdES-BES signature as the last child of the root element.
+/// `sign` gets the SHA-256 of the canonical SignedInfo and returns the raw RSA signature.
+pub fn sign<F>(xml: &str, certificate: &Certificate, sign: F) -> Result<String, String>
+where
+    F: FnOnce(&[u8; 32]) -> Result<Vec<u8>, String>,
+{
+    let xml = xml.replace("\r\n", "\n").replace('\r', "\n");
+    let document = c14n::parse(&xml)?;
+    let document_digest = base64::encode(Sha256::digest(c14n::canonicalize(&document)));
+
+    let now = SystemTime::now();
+    let signing_time = xsd_date_time(now)?;
+    let id = format!("xades-{}", &hex_string(&Sha256::digest([document_digest.as_bytes(), signing_time.as_bytes()].concat()))[..16]);
+
+    let properties = signed_properties(&id, certificate, &signing_time)?;
+    let properties_digest = base64::encode(Sha256::digest(&properties));
+    let signed_info = signed_info(&id, &document_digest, &properties_digest);
+    let signature_value = sign(&Sha256::digest(&signed_info).into())?;
+
+    let certificate_der = certificate.to_der().map_err(|err| err.to_string())?;
+    let signature = format!(concat!(
+        "<ds:Signature xmlns:ds=\"{ds}\" Id=\"{id}\">",
+        "{signed_info}",
+        "<ds:SignatureValue>{value}</ds:SignatureValue>",
+        "<ds:KeyInfo><ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data></ds:KeyInfo>",
+        "<ds:Object><xades:QualifyingProperties xmlns:xades=\"{xades}\" Target=\"#{id}\">",
+        "{properties}",
+        "</xades:QualifyingProperties></ds:Object>",
+        "</ds:Signature>"),
+        ds = XMLDSIG_NS, xades = XADES_NS, id = id, signed_info = signed_info,
+        value = base64::encode(signature_value), certificate = base64::encode(certificate_der),
+        properties = properties);
+
+    let root_end = document.root_end.clone();
+    let mut signed = String::with_capacity(xml.len() + signature.len());
+    if xml[root_end.clone()].starts_with("/>") {
+        signed.push_str(&xml[..root_end.start]);
+        signed.push('>');
+        signed.push_str(&signature);
+        signed.push_str(&format!("</{}>", document.root.name));
+    } else {
+        signed.push_str(&xml[..root_end.start]);
+        signed.push_str(&signature);
+        signed.push_str(&xml[root_end.start..root_end.end]);
+    }
+    signed.push_str(&xml[root_end.end..]);
+    Ok(signed)
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::testing::{self, DAY};
+    use crate::signature::verify_rsa_sha256;
+    use c14n::{Element, Node};
+
+    fn take_child(element: &mut Element, name: &str) -> Element {
+        let index = element.children.iter()
+            .position(|node| matches!(node, Node::Element(child) if child.name == name))
+            .unwrap_or_else(|| panic!("{} has no {}", element.name, name));
+        match element.children.remove(index) {
+            Node::Element(child) => child,
+            _ => unreachable!(),
+        }
+    }
+
+    fn text(element: &Element) -> String {
+        element.children.iter().map(|node| match node {
+            Node::Text(text) => text.as_str(),
+            _ => "",
+        }).collect()
+    }
+
+    fn canonical(root: Element) -> String {
+        c14n::canonicalize(&c14n::Document { prolog: vec![], root, epilog: vec![], root_end: 0..0 })
+    }
+
+    #[test]
+    fn signature_verifies_against_its_own_references() {
+        let ca = testing::root("CN=Test CA,O=Test", testing::key(0));
+        let now = SystemTime::now();
+        let certificate = testing::leaf(&ca, testing::key(0), 2, now - DAY, now + 365 * DAY);
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<id:card xmlns:id=\"urn:test\" b=\"2\" a=\"1\">\n  <id:name>Petar &amp; Jovana</id:name>\n  <id:empty/>\n</id:card>\n";
+
+        let signed = sign(xml, &certificate, |digest| testing::sign_digest(testing::key(1), digest)).unwrap();
+        assert!(signed.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<id:card xmlns:id=\"urn:test\" b=\"2\" a=\"1\">"));
+        assert!(signed.ends_with("</ds:Signature></id:card>\n"));
+
+        let mut document = c14n::parse(&signed).unwrap();
+        let mut signature = take_child(&mut document.root, "ds:Signature");
+
+        // Enveloped signature transform: the document without the signature is what was digested
+        let enveloped = c14n::canonicalize(&document);
+        assert_eq!(enveloped, c14n::canonicalize(&c14n::parse(xml).unwrap()));
+
+        let mut object = take_child(&mut signature, "ds:Object");
+        let mut qualifying = take_child(&mut object, "xades:QualifyingProperties");
+        let properties = take_child(&mut qualifying, "xades:SignedProperties");
+        let mut key_info = take_child(&mut signature, "ds:KeyInfo");
+        let mut x509_data = take_child(&mut key_info, "ds:X509Data");
+        let embedded = take_child(&mut x509_data, "ds:X509Certificate");
+        assert_eq!(base64::decode(text(&embedded)).unwrap(), certificate.to_der().unwrap());
+
+        let signature_value = base64::decode(text(&take_child(&mut signature, "ds:SignatureValue"))).unwrap();
+        let signed_info = take_child(&mut signature, "ds:SignedInfo");
+        let digests: Vec<String> = signed_info.children.iter()
+            .filter_map(|node| match node {
+                Node::Element(reference) if reference.name == "ds:Reference" => Some(reference),
+                _ => None,
+            })
+            .map(|reference| {
+                let digest = reference.children.iter().find_map(|node| match node {
+                    Node::Element(child) if child.name == "ds:DigestValue" => Some(text(child)),
+                    _ => None,
+                });
+                digest.unwrap()
+            })
+            .collect();
+        assert_eq!(digests, [
+            base64::encode(Sha256::digest(enveloped)),
+            base64::encode(Sha256::digest(canonical(properties))),
+        ]);
+
+        let signed_info_digest: [u8; 32] = Sha256::digest(canonical(signed_info)).into();
+        verify_rsa_sha256(&certificate, &signed_info_digest, &signature_value).unwrap();
+    }
+}